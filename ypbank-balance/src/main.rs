@@ -0,0 +1,242 @@
+mod statement;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use ypbank_parser::{
+    Balance, BinaryFormat, CsvFormat, Format, Ledger, Statement, TextFormat, Transaction,
+    UnknownCodePolicy,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Балансы пользователей по файлу транзакций")]
+struct Args {
+    /// Входной файл
+    #[arg(short, long)]
+    input: String,
+
+    /// Формат входного файла
+    #[arg(long = "input-format")]
+    input_format: FormatType,
+
+    /// Учитывать только транзакции с TIMESTAMP не позже указанного
+    #[arg(long = "as-of")]
+    as_of: Option<u64>,
+
+    /// Показать баланс только одного пользователя
+    #[arg(long)]
+    user: Option<u64>,
+
+    /// Формат отчёта; по умолчанию `table`
+    #[arg(long = "output-format")]
+    output_format: Option<ReportFormat>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Выписка по счёту пользователя за период
+    Statement(StatementArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct StatementArgs {
+    /// Пользователь, по счёту которого строится выписка
+    #[arg(long)]
+    user: u64,
+
+    /// Начало периода (TIMESTAMP включительно)
+    #[arg(long)]
+    from: Option<u64>,
+
+    /// Конец периода (TIMESTAMP включительно)
+    #[arg(long)]
+    to: Option<u64>,
+
+    /// Формат выписки
+    #[arg(long = "output-format", default_value = "text")]
+    output_format: StatementFormat,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum FormatType {
+    Binary,
+    Csv,
+    Text,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ReportFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum StatementFormat {
+    Text,
+    Csv,
+    Html,
+}
+
+fn write_table<W: Write>(mut writer: W, balances: &[(u64, Balance)]) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{:>20} {:>20} {:>20} {:>20} {:>20}",
+        "USER_ID", "SETTLED", "HELD_OUT", "HELD_IN", "AVAILABLE"
+    )?;
+    for (user_id, balance) in balances {
+        writeln!(
+            writer,
+            "{:>20} {:>20} {:>20} {:>20} {:>20}",
+            user_id,
+            balance.settled,
+            balance.held_out,
+            balance.held_in,
+            available(balance, OVERFLOW)
+        )?;
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write>(mut writer: W, balances: &[(u64, Balance)]) -> io::Result<()> {
+    writeln!(writer, "USER_ID,SETTLED,HELD_OUT,HELD_IN,AVAILABLE")?;
+    for (user_id, balance) in balances {
+        writeln!(
+            writer,
+            "{},{},{},{},{}",
+            user_id,
+            balance.settled,
+            balance.held_out,
+            balance.held_in,
+            available(balance, OVERFLOW)
+        )?;
+    }
+    Ok(())
+}
+
+fn write_json<W: Write>(mut writer: W, balances: &[(u64, Balance)]) -> io::Result<()> {
+    writeln!(writer, "[")?;
+    for (i, (user_id, balance)) in balances.iter().enumerate() {
+        let separator = if i + 1 < balances.len() { "," } else { "" };
+        writeln!(
+            writer,
+            "  {{\"user_id\": {}, \"settled\": {}, \"held_out\": {}, \"held_in\": {}, \"available\": {}}}{}",
+            user_id,
+            balance.settled,
+            balance.held_out,
+            balance.held_in,
+            available(balance, "null"),
+            separator
+        )?;
+    }
+    writeln!(writer, "]")?;
+    Ok(())
+}
+
+/// Отметка в таблице и CSV для доступного остатка, который не помещается в i64
+const OVERFLOW: &str = "OVERFLOW";
+
+/// Доступный остаток для отчёта; `overflow` выводится, если он не помещается в i64
+fn available(balance: &Balance, overflow: &str) -> String {
+    balance
+        .available()
+        .map_or_else(|| overflow.to_string(), |value| value.to_string())
+}
+
+fn run_statement(
+    transactions: &[Transaction],
+    args: &StatementArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let statement = Statement::build(transactions, args.user, args.from, args.to)?;
+
+    let stdout = io::stdout();
+    let writer = BufWriter::new(stdout);
+    match args.output_format {
+        StatementFormat::Text => statement::write_text(writer, &statement)?,
+        StatementFormat::Csv => statement::write_csv(writer, &statement)?,
+        StatementFormat::Html => statement::write_html(writer, &statement)?,
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    // Флаги отчёта о балансах до подкоманды выписка не учитывает
+    let report_flags = args.as_of.is_some() || args.user.is_some() || args.output_format.is_some();
+    if args.command.is_some() && report_flags {
+        return Err(
+            "--as-of, --user и --output-format отчёта о балансах не применяются \
+                    к statement; укажите --user, --to и --output-format после statement"
+                .into(),
+        );
+    }
+
+    let input_file = File::open(&args.input)?;
+    let reader = BufReader::new(input_file);
+
+    // Неизвестные коды не учитываются в балансах, но и не прерывают отчёт
+    let codes = UnknownCodePolicy::Preserve;
+    let mut transactions = match args.input_format {
+        FormatType::Binary => BinaryFormat::new()
+            .with_unknown_codes(codes)
+            .read_from(reader)?,
+        FormatType::Csv => CsvFormat::new()
+            .with_unknown_codes(codes)
+            .read_from(reader)?,
+        FormatType::Text => TextFormat::new()
+            .with_unknown_codes(codes)
+            .read_from(reader)?,
+    };
+
+    if let Some(Command::Statement(statement_args)) = &args.command {
+        return run_statement(&transactions, statement_args);
+    }
+
+    if let Some(as_of) = args.as_of {
+        transactions.retain(|t| t.timestamp <= as_of);
+    }
+    transactions.sort_by_key(|t| t.timestamp);
+
+    let mut ledger = Ledger::new();
+    ledger.apply_all(&transactions)?;
+
+    for overdraft in ledger
+        .overdrafts()
+        .iter()
+        .filter(|o| args.user.is_none_or(|user_id| o.user_id == user_id))
+    {
+        eprintln!(
+            "Овердрафт: пользователь {}, транзакция {}, доступно {}",
+            overdraft.user_id, overdraft.tx_id, overdraft.available
+        );
+    }
+    if ledger.skipped() > 0 {
+        eprintln!(
+            "Пропущено транзакций с неизвестным типом или статусом: {}",
+            ledger.skipped()
+        );
+    }
+
+    let balances: Vec<(u64, Balance)> = match args.user {
+        Some(user_id) => vec![(user_id, ledger.balance(user_id))],
+        None => ledger
+            .balances()
+            .iter()
+            .map(|(&user_id, &balance)| (user_id, balance))
+            .collect(),
+    };
+
+    let stdout = io::stdout();
+    let writer = BufWriter::new(stdout);
+    match args.output_format.unwrap_or(ReportFormat::Table) {
+        ReportFormat::Table => write_table(writer, &balances)?,
+        ReportFormat::Csv => write_csv(writer, &balances)?,
+        ReportFormat::Json => write_json(writer, &balances)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,198 @@
+use std::io::{self, Write};
+use ypbank_parser::Statement;
+
+/// Выводит выписку простым текстом
+pub fn write_text<W: Write>(mut writer: W, statement: &Statement) -> io::Result<()> {
+    writeln!(writer, "Выписка по счёту {}", statement.user_id)?;
+    writeln!(
+        writer,
+        "Период: {} — {}",
+        bound(statement.from),
+        bound(statement.to)
+    )?;
+    writeln!(writer, "Входящий остаток: {}", statement.opening_balance)?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "{:>20} {:>14} {:<10} {:<10} {:>20} {:>20}  DESCRIPTION",
+        "TX_ID", "TIMESTAMP", "TX_TYPE", "STATUS", "DELTA", "BALANCE"
+    )?;
+    for entry in &statement.entries {
+        let transaction = &entry.transaction;
+        writeln!(
+            writer,
+            "{:>20} {:>14} {:<10} {:<10} {:>20} {:>20}  {}",
+            transaction.tx_id,
+            transaction.timestamp,
+            transaction.tx_type.to_string(),
+            transaction.status.to_string(),
+            entry.delta,
+            entry.running_balance,
+            escape_text(&transaction.description)
+        )?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "Итоги по типам:")?;
+    for (tx_type, total) in &statement.totals {
+        writeln!(
+            writer,
+            "  {:<10} {:>6} шт. {:>20}",
+            tx_type.to_string(),
+            total.count,
+            total.amount
+        )?;
+    }
+    writeln!(writer, "Исходящий остаток: {}", statement.closing_balance)?;
+    Ok(())
+}
+
+/// Выводит строки выписки в CSV; остатки и итоги — отдельными строками
+/// с пустым TX_ID и пояснением в DESCRIPTION
+pub fn write_csv<W: Write>(mut writer: W, statement: &Statement) -> io::Result<()> {
+    writeln!(
+        writer,
+        "TX_ID,TIMESTAMP,TX_TYPE,STATUS,FROM_USER_ID,TO_USER_ID,AMOUNT,DELTA,BALANCE,DESCRIPTION"
+    )?;
+    writeln!(
+        writer,
+        ",,,,,,,,{},\"OPENING_BALANCE\"",
+        statement.opening_balance
+    )?;
+    for entry in &statement.entries {
+        let transaction = &entry.transaction;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},\"{}\"",
+            transaction.tx_id,
+            transaction.timestamp,
+            transaction.tx_type,
+            transaction.status,
+            transaction.from_user_id,
+            transaction.to_user_id,
+            transaction.amount,
+            entry.delta,
+            entry.running_balance,
+            transaction.description.replace('"', "\"\"")
+        )?;
+    }
+    for (tx_type, total) in &statement.totals {
+        writeln!(
+            writer,
+            ",,{},,,,,{},,\"TOTAL ({} шт.)\"",
+            tx_type, total.amount, total.count
+        )?;
+    }
+    writeln!(
+        writer,
+        ",,,,,,,,{},\"CLOSING_BALANCE\"",
+        statement.closing_balance
+    )?;
+    Ok(())
+}
+
+/// Выводит выписку отдельной HTML-страницей
+pub fn write_html<W: Write>(mut writer: W, statement: &Statement) -> io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html lang=\"ru\">")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(
+        writer,
+        "<title>Выписка по счёту {}</title>",
+        statement.user_id
+    )?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<h1>Выписка по счёту {}</h1>", statement.user_id)?;
+    writeln!(
+        writer,
+        "<p>Период: {} — {}</p>",
+        bound(statement.from),
+        bound(statement.to)
+    )?;
+    writeln!(
+        writer,
+        "<p>Входящий остаток: {}</p>",
+        statement.opening_balance
+    )?;
+    writeln!(writer, "<table>")?;
+    writeln!(
+        writer,
+        "<tr><th>TX_ID</th><th>TIMESTAMP</th><th>TX_TYPE</th><th>STATUS</th>\
+         <th>DELTA</th><th>BALANCE</th><th>DESCRIPTION</th></tr>"
+    )?;
+    for entry in &statement.entries {
+        let transaction = &entry.transaction;
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            transaction.tx_id,
+            transaction.timestamp,
+            transaction.tx_type,
+            transaction.status,
+            entry.delta,
+            entry.running_balance,
+            escape_html(&transaction.description)
+        )?;
+    }
+    writeln!(writer, "</table>")?;
+    writeln!(writer, "<h2>Итоги по типам</h2>")?;
+    writeln!(writer, "<table>")?;
+    writeln!(
+        writer,
+        "<tr><th>TX_TYPE</th><th>COUNT</th><th>AMOUNT</th></tr>"
+    )?;
+    for (tx_type, total) in &statement.totals {
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            tx_type, total.count, total.amount
+        )?;
+    }
+    writeln!(writer, "</table>")?;
+    writeln!(
+        writer,
+        "<p>Исходящий остаток: {}</p>",
+        statement.closing_balance
+    )?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")?;
+    Ok(())
+}
+
+/// Граница периода для заголовка выписки
+fn bound(value: Option<u64>) -> String {
+    value.map_or_else(|| "…".to_string(), |value| value.to_string())
+}
+
+/// Экранирует описание для строки текстовой таблицы
+///
+/// Перевод строки разорвал бы строку выписки, поэтому он и обратная косая
+/// черта записываются как в строковых литералах.
+fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,7 +1,7 @@
 use clap::{Parser, ValueEnum};
 use std::fs::File;
 use std::io::BufReader;
-use ypbank_parser::{BinaryFormat, CsvFormat, Format, TextFormat, Transaction};
+use ypbank_parser::{BinaryFormat, CsvFormat, Format, Query, TextFormat, Transaction};
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -16,6 +16,10 @@ struct Args {
 
     #[arg(long)]
     format2: FormatType,
+
+    /// Сравнивать только транзакции, удовлетворяющие выражению
+    #[arg(long = "where", value_parser = Query::parse)]
+    query: Option<Query>,
 }
 
 #[derive(ValueEnum, Clone, Debug)]
@@ -28,22 +32,26 @@ enum FormatType {
 fn read_transactions(
     filename: &str,
     format_type: FormatType,
+    query: Option<&Query>,
 ) -> Result<Vec<Transaction>, Box<dyn std::error::Error>> {
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
-    let transactions = match format_type {
-        FormatType::Binary => BinaryFormat.read_from(reader)?,
-        FormatType::Csv => CsvFormat.read_from(reader)?,
-        FormatType::Text => TextFormat.read_from(reader)?,
+    let mut transactions = match format_type {
+        FormatType::Binary => BinaryFormat::new().read_from(reader)?,
+        FormatType::Csv => CsvFormat::new().read_from(reader)?,
+        FormatType::Text => TextFormat::new().read_from(reader)?,
     };
+    if let Some(query) = query {
+        transactions.retain(|transaction| query.matches(transaction));
+    }
     Ok(transactions)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
-    let transactions1 = read_transactions(&args.file1, args.format1)?;
-    let transactions2 = read_transactions(&args.file2, args.format2)?;
+    let transactions1 = read_transactions(&args.file1, args.format1, args.query.as_ref())?;
+    let transactions2 = read_transactions(&args.file2, args.format2, args.query.as_ref())?;
 
     if transactions1 == transactions2 {
         println!("Транзакции {} и {} одинаковы.", args.file1, args.file2);
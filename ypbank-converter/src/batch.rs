@@ -0,0 +1,257 @@
+use crate::inputs::{self, Input};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use ypbank_parser::FormatKind;
+
+/// Файл, который нужно сконвертировать
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub input: Input,
+    pub output: PathBuf,
+}
+
+/// План пакетной конвертации каталога
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub jobs: Vec<Job>,
+    /// Файлы, формат которых не распознан
+    pub skipped: Vec<PathBuf>,
+    /// Файлы и каталоги, которые нельзя конвертировать или прочитать, с причиной
+    pub rejected: Vec<(PathBuf, String)>,
+}
+
+/// Составляет план конвертации всех файлов каталога `input_dir`
+///
+/// Каталог обходится рекурсивно; скрытые файлы и каталоги, а также
+/// `output_dir`, если он лежит внутри `input_dir`, пропускаются.
+/// Символические ссылки разыменовываются, каждый каталог обходится один
+/// раз. Вложенные каталоги и файлы, которые не удалось прочитать, попадают
+/// в `rejected`, а ошибкой завершается только чтение самого `input_dir`. Выходной
+/// файл получает тот же относительный путь и расширение выходного формата.
+/// `format` задаёт формат всех входных файлов; без него формат
+/// определяется по содержимому, а нераспознанные файлы пропускаются.
+pub fn plan(
+    input_dir: &Path,
+    output_dir: &Path,
+    format: Option<FormatKind>,
+    output_format: FormatKind,
+) -> io::Result<Plan> {
+    let exclude = fs::canonicalize(output_dir).ok();
+    let mut walk = Walk {
+        exclude: exclude.as_deref(),
+        visited: HashSet::new(),
+        files: Vec::new(),
+        rejected: Vec::new(),
+    };
+    walk.dir(input_dir)?;
+
+    let mut plan = Plan {
+        rejected: walk.rejected,
+        ..Plan::default()
+    };
+    let files = walk.files;
+    let mut targets: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for path in files {
+        let detected = match format {
+            Some(format) => Some(format),
+            None => match inputs::detect(&path) {
+                Ok(detected) => detected,
+                Err(e) => {
+                    plan.rejected.push((path, e.to_string()));
+                    continue;
+                }
+            },
+        };
+        let Some(detected) = detected else {
+            plan.skipped.push(path);
+            continue;
+        };
+
+        let relative = path.strip_prefix(input_dir).unwrap_or(&path);
+        let output = output_dir
+            .join(relative)
+            .with_extension(extension(output_format));
+        if let Some(first) = targets.get(&output) {
+            let message = format!(
+                "выходной файл {} уже получается из {}",
+                output.display(),
+                first.display()
+            );
+            plan.rejected.push((path, message));
+            continue;
+        }
+        targets.insert(output.clone(), path.clone());
+
+        plan.jobs.push(Job {
+            input: Input {
+                path: path.to_string_lossy().into_owned(),
+                format: Some(detected),
+            },
+            output,
+        });
+    }
+    Ok(plan)
+}
+
+/// Обход каталога: найденные файлы и пути, которые не удалось прочитать
+struct Walk<'a> {
+    exclude: Option<&'a Path>,
+    /// Канонические пути уже обойдённых каталогов: ссылка на предка
+    /// не зацикливает обход
+    visited: HashSet<PathBuf>,
+    files: Vec<PathBuf>,
+    rejected: Vec<(PathBuf, String)>,
+}
+
+impl Walk<'_> {
+    /// Собирает файлы каталога в алфавитном порядке
+    ///
+    /// Ошибка чтения вложенного каталога или элемента записывается в
+    /// `rejected`, и обход продолжается.
+    fn dir(&mut self, dir: &Path) -> io::Result<()> {
+        let canonical = fs::canonicalize(dir)?;
+        if self.exclude == Some(canonical.as_path()) || !self.visited.insert(canonical) {
+            return Ok(());
+        }
+
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            match entry {
+                Ok(entry) => entries.push(entry.path()),
+                Err(e) => self.rejected.push((dir.to_path_buf(), e.to_string())),
+            }
+        }
+        entries.sort();
+
+        for path in entries {
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden {
+                continue;
+            }
+            // Ссылки разыменовываются; висячая ссылка — ошибка этого пути
+            let is_dir = match fs::metadata(&path) {
+                Ok(metadata) => metadata.is_dir(),
+                Err(e) => {
+                    self.rejected.push((path, e.to_string()));
+                    continue;
+                }
+            };
+            if !is_dir {
+                self.files.push(path);
+            } else if let Err(e) = self.dir(&path) {
+                self.rejected.push((path, e.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Расширение файлов формата
+fn extension(format: FormatKind) -> &'static str {
+    match format {
+        FormatKind::Binary => "bin",
+        FormatKind::Csv => "csv",
+        FormatKind::Text => "txt",
+    }
+}
+
+/// Итог конвертации одного файла
+#[derive(Debug)]
+pub struct Outcome {
+    pub job: Job,
+    /// Число записанных транзакций или текст ошибки
+    pub result: Result<u64, String>,
+    pub elapsed: Duration,
+}
+
+/// Конвертирует файлы в `workers` потоков
+///
+/// Ошибка в одном файле не останавливает остальные. Итоги возвращаются
+/// в порядке плана, а не завершения.
+pub fn run<F>(jobs: &[Job], workers: usize, convert: F) -> Vec<Outcome>
+where
+    F: Fn(&Job) -> Result<u64, Box<dyn Error>> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::with_capacity(jobs.len()));
+    let workers = workers.clamp(1, jobs.len().max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(index) else {
+                        break;
+                    };
+                    let started = Instant::now();
+                    let result = convert(job).map_err(|e| e.to_string());
+                    let outcome = Outcome {
+                        job: job.clone(),
+                        result,
+                        elapsed: started.elapsed(),
+                    };
+                    outcomes
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push((index, outcome));
+                }
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap_or_else(|e| e.into_inner());
+    outcomes.sort_by_key(|(index, _)| *index);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+/// Печатает отчёт: по строке на файл и сводку
+///
+/// Возвращает число файлов, которые не удалось сконвертировать.
+pub fn report(plan: &Plan, outcomes: &[Outcome], elapsed: Duration) -> usize {
+    let mut converted = 0;
+    let mut records = 0;
+    let mut failed = plan.rejected.len();
+
+    for outcome in outcomes {
+        let input = &outcome.job.input.path;
+        match &outcome.result {
+            Ok(count) => {
+                converted += 1;
+                records += count;
+                println!(
+                    "{input} -> {}\t{count}\t{:.2} с",
+                    outcome.job.output.display(),
+                    outcome.elapsed.as_secs_f64()
+                );
+            }
+            Err(message) => {
+                failed += 1;
+                eprintln!("Ошибка: {input}: {message}");
+            }
+        }
+    }
+    for (path, message) in &plan.rejected {
+        eprintln!("Ошибка: {}: {message}", path.display());
+    }
+    for path in &plan.skipped {
+        eprintln!("Пропущен: {}: формат не распознан", path.display());
+    }
+
+    println!(
+        "Сконвертировано файлов: {converted}, с ошибками: {failed}, пропущено: {}",
+        plan.skipped.len()
+    );
+    println!("Транзакций: {records}");
+    println!("Время: {:.2} с", elapsed.as_secs_f64());
+    failed
+}
@@ -0,0 +1,261 @@
+use crate::Records;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read};
+use std::path::Path;
+use std::rc::Rc;
+use ypbank_parser::{
+    BinaryFormat, CsvFormat, Format, FormatKind, ParseError, ParseResult, TextFormat,
+    TrailerPolicy, Transaction, UnknownCodePolicy,
+};
+
+/// Путь, обозначающий стандартный ввод
+pub const STDIN: &str = "-";
+/// Предел начала источника, которое читается для определения формата
+const PEEK_LIMIT: usize = 64 * 1024;
+
+/// Как читать входные файлы
+#[derive(Debug, Clone, Copy)]
+pub struct InputOptions {
+    pub unknown_codes: UnknownCodePolicy,
+    pub trailer: TrailerPolicy,
+}
+
+/// Входной файл и его формат; `None` — определить по содержимому
+#[derive(Debug, Clone)]
+pub struct Input {
+    pub path: String,
+    pub format: Option<FormatKind>,
+}
+
+/// Раскрывает шаблоны путей и сопоставляет файлам форматы
+///
+/// Форматов может быть ноль (все определяются по содержимому), один (для
+/// всех файлов) или по одному на каждый аргумент `--input`; файлы,
+/// найденные по шаблону, получают формат своего аргумента.
+pub fn resolve(patterns: &[String], formats: &[FormatKind]) -> Result<Vec<Input>, Box<dyn Error>> {
+    let format_for = |index: usize| match formats.len() {
+        0 => Ok(None),
+        1 => Ok(Some(formats[0])),
+        n if n == patterns.len() => Ok(Some(formats[index])),
+        n => Err(format!(
+            "Указано форматов: {n}, входных файлов: {}; нужен один формат или по одному на файл",
+            patterns.len()
+        )),
+    };
+
+    let mut inputs = Vec::new();
+    for (index, pattern) in patterns.iter().enumerate() {
+        let format = format_for(index)?;
+        for path in expand(pattern)? {
+            inputs.push(Input { path, format });
+        }
+    }
+    Ok(inputs)
+}
+
+/// Раскрывает шаблон `*`, `?`, `[...]`; обычный путь возвращается как есть
+fn expand(pattern: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![pattern.to_string()]);
+    }
+
+    let mut paths = Vec::new();
+    for path in glob::glob(pattern)? {
+        paths.push(path?.to_string_lossy().into_owned());
+    }
+    if paths.is_empty() {
+        return Err(format!("Шаблон {pattern} не совпал ни с одним файлом").into());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Открывает входной файл и читает его потоком
+pub fn open(input: &Input, options: InputOptions) -> Result<Records, Box<dyn Error>> {
+    let mut source: Box<dyn Read> = if input.path == STDIN {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(&input.path)?)
+    };
+
+    // Прочитанное для определения формата возвращается перед остатком источника
+    let (format, prefix) = match input.format {
+        Some(format) => (format, Vec::new()),
+        None => {
+            let prefix = peek(&mut source)?;
+            let format = FormatKind::detect(&prefix).ok_or_else(|| {
+                format!(
+                    "Не удалось определить формат файла {}, укажите --input-format",
+                    input.path
+                )
+            })?;
+            (format, prefix)
+        }
+    };
+    let reader = BufReader::new(Cursor::new(prefix).chain(source));
+
+    let records: Records = match format {
+        FormatKind::Binary => Box::new(
+            BinaryFormat::new()
+                .with_unknown_codes(options.unknown_codes)
+                .with_trailer_policy(options.trailer)
+                .records(reader),
+        ),
+        FormatKind::Csv => Box::new(
+            CsvFormat::new()
+                .with_unknown_codes(options.unknown_codes)
+                .with_trailer_policy(options.trailer)
+                .records(reader),
+        ),
+        FormatKind::Text => Box::new(
+            TextFormat::new()
+                .with_unknown_codes(options.unknown_codes)
+                .with_trailer_policy(options.trailer)
+                .records(reader),
+        ),
+    };
+    Ok(records)
+}
+
+/// Определяет формат файла по его началу; `None` — формат не распознан
+pub fn detect(path: &Path) -> io::Result<Option<FormatKind>> {
+    Ok(FormatKind::detect(&peek(&mut File::open(path)?)?))
+}
+
+/// Читает начало источника, достаточное для определения формата
+///
+/// Из канала `read` может вернуть всего несколько байт, поэтому чтение
+/// продолжается, пока не прочитаны магическое число или одна целая
+/// значимая строка, источник не кончился или не достигнут `PEEK_LIMIT`.
+fn peek<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut prefix = Vec::new();
+    let mut chunk = [0u8; 8192];
+    while prefix.len() < PEEK_LIMIT && !enough_to_detect(&prefix) {
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => prefix.extend_from_slice(&chunk[..read]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(prefix)
+}
+
+/// Начала хватает, чтобы формат не зависел от следующих байт
+fn enough_to_detect(prefix: &[u8]) -> bool {
+    if prefix.len() < 4 {
+        return false;
+    }
+    if FormatKind::detect(&prefix[..4]) == Some(FormatKind::Binary) {
+        return true;
+    }
+    let Some(end) = prefix.iter().rposition(|&byte| byte == b'\n') else {
+        return false;
+    };
+    String::from_utf8_lossy(&prefix[..end])
+        .lines()
+        .map(str::trim)
+        .any(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Как поступать с TX_ID, который встречается в разных входных файлах
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    Ignore,
+    Warn,
+    Error,
+}
+
+/// Поиск TX_ID, которые встречаются в нескольких входных файлах
+///
+/// Хранит номер файла для каждого встреченного TX_ID. Повторы внутри
+/// одного файла не считаются коллизией, для них есть `--dedupe`.
+pub struct CollisionCheck {
+    names: Vec<String>,
+    seen: HashMap<u64, usize>,
+    policy: CollisionPolicy,
+}
+
+impl CollisionCheck {
+    pub fn new(inputs: &[Input], policy: CollisionPolicy) -> Self {
+        CollisionCheck {
+            names: inputs.iter().map(|input| input.path.clone()).collect(),
+            seen: HashMap::new(),
+            policy,
+        }
+    }
+
+    /// Запоминает TX_ID транзакции из файла `input`
+    fn check(&mut self, input: usize, transaction: &Transaction) -> ParseResult<()> {
+        let first = *self.seen.entry(transaction.tx_id).or_insert(input);
+        if first == input {
+            return Ok(());
+        }
+
+        let tx_id = transaction.tx_id;
+        let first = self.names[first].clone();
+        let second = self.names[input].clone();
+        match self.policy {
+            CollisionPolicy::Ignore => Ok(()),
+            CollisionPolicy::Warn => {
+                eprintln!("Предупреждение: TX_ID {tx_id} встречается в {first} и {second}");
+                Ok(())
+            }
+            CollisionPolicy::Error => Err(ParseError::TxIdCollision {
+                tx_id,
+                first,
+                second,
+            }),
+        }
+    }
+
+    /// Проверяет транзакции файла `input` по мере чтения
+    pub fn watch(check: &Rc<RefCell<Self>>, input: usize, records: Records) -> Records {
+        let check = Rc::clone(check);
+        Box::new(records.map(move |record| {
+            let transaction = record?;
+            check.borrow_mut().check(input, &transaction)?;
+            Ok(transaction)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Источник, который отдаёт по одному байту, как медленный канал
+    struct ByteByByte<'a>(&'a [u8]);
+
+    impl Read for ByteByByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_peek_reads_until_detectable() -> io::Result<()> {
+        let csv = b"\n  \nTX_ID,TX_TYPE\n1,DEPOSIT\n";
+        let prefix = peek(&mut ByteByByte(csv))?;
+        assert_eq!(prefix, b"\n  \nTX_ID,TX_TYPE\n");
+        assert_eq!(FormatKind::detect(&prefix), Some(FormatKind::Csv));
+
+        let text = b"# comment\nTX_ID: 1\n";
+        assert_eq!(peek(&mut ByteByByte(text))?, text);
+
+        let binary = b"YPBN\0\0\0\x10";
+        assert_eq!(peek(&mut ByteByByte(binary))?, b"YPBN");
+
+        // Строка без перевода строки дочитывается до конца источника
+        assert_eq!(peek(&mut ByteByByte(b"TX_ID: 1"))?, b"TX_ID: 1");
+        Ok(())
+    }
+}
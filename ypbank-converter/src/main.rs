@@ -1,22 +1,177 @@
-use clap::{Parser, ValueEnum};
-use std::fs::File;
-use std::io::{self, BufReader, BufWriter};
-use ypbank_parser::{BinaryFormat, CsvFormat, Format, TextFormat};
+mod batch;
+mod inputs;
+mod output;
+mod split;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use inputs::{CollisionCheck, CollisionPolicy, InputOptions};
+use output::{OutputOptions, write_file, write_records};
+use split::Splitter;
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+use std::time::Instant;
+use ypbank_parser::{
+    CsvColumns, Deduplicated, DuplicatePolicy, ExternalSorter, FormatKind, MergedRecords,
+    ParseResult, PathTemplate, Query, SortKey, SortOrder, TrailerPolicy, Transaction,
+    TransactionFilter, TransactionStatus, TransactionType, UnknownCodePolicy,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Конвертер финансовых данных между форматами")]
 struct Args {
-    /// Входной файл
-    #[arg(short, long)]
-    input: String,
+    /// Входные файлы или шаблоны путей, например `data/*.csv`;
+    /// `-` или отсутствие аргумента — чтение из stdin
+    #[arg(short, long, num_args = 1..)]
+    input: Vec<String>,
+
+    /// Формат входных файлов: один для всех или по одному на каждый
+    /// `--input`; без него формат определяется по содержимому
+    #[arg(long = "input-format", num_args = 1..)]
+    input_format: Vec<FormatType>,
 
-    /// Формат входного файла
-    #[arg(long = "input-format")]
-    input_format: FormatType,
+    /// Слить входные файлы, упорядоченные по TIMESTAMP, сохраняя порядок
+    #[arg(long = "merge-sorted")]
+    merge_sorted: bool,
+
+    /// Что делать с TX_ID, который встречается в разных входных файлах
+    #[arg(long = "on-collision", default_value = "warn")]
+    on_collision: CollisionAction,
 
     /// Формат выходного файла
     #[arg(long = "output-format")]
     output_format: FormatType,
+
+    /// Выходной файл; без него результат выводится в stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Дописать в конец итог с числом записей и суммой
+    #[arg(long)]
+    trailer: bool,
+
+    /// Начать бинарный файл с заголовка: версия, время создания, производитель
+    #[arg(long = "binary-header")]
+    binary_header: bool,
+
+    /// Построить индекс бинарного файла для произвольного доступа (`<файл>.idx`)
+    #[arg(long)]
+    index: bool,
+
+    /// Требовать итог во входных файлах; без него файл считается обрезанным
+    #[arg(long = "require-trailer")]
+    require_trailer: bool,
+
+    /// Прочитать записанный файл и сравнить с исходными транзакциями
+    #[arg(long)]
+    verify: bool,
+
+    /// Каталог, все распознанные файлы которого нужно сконвертировать
+    #[arg(long = "input-dir", requires = "output_dir", conflicts_with_all = ["input", "output", "merge_sorted"])]
+    input_dir: Option<PathBuf>,
+
+    /// Каталог для результатов пакетной конвертации
+    #[arg(long = "output-dir", requires = "input_dir")]
+    output_dir: Option<PathBuf>,
+
+    /// Число файлов, конвертируемых одновременно; по умолчанию — число ядер
+    #[arg(short, long, requires = "input_dir")]
+    jobs: Option<usize>,
+
+    /// Сохранять неизвестные коды типа и статуса вместо ошибки
+    #[arg(long = "keep-unknown-codes")]
+    keep_unknown_codes: bool,
+
+    /// Оставить транзакции указанных типов (через запятую)
+    #[arg(long = "type", value_delimiter = ',', value_parser = parse_type)]
+    types: Vec<TransactionType>,
+
+    /// Оставить транзакции с указанными статусами (через запятую)
+    #[arg(long = "status", value_delimiter = ',', value_parser = parse_status)]
+    statuses: Vec<TransactionStatus>,
+
+    /// Оставить транзакции, где пользователь — отправитель или получатель
+    #[arg(long = "user", value_delimiter = ',')]
+    users: Vec<u64>,
+
+    /// Минимальная сумма включительно
+    #[arg(long = "min-amount")]
+    min_amount: Option<i64>,
+
+    /// Максимальная сумма включительно
+    #[arg(long = "max-amount")]
+    max_amount: Option<i64>,
+
+    /// Начало периода включительно (миллисекунды Unix)
+    #[arg(long)]
+    since: Option<u64>,
+
+    /// Конец периода включительно (миллисекунды Unix)
+    #[arg(long)]
+    until: Option<u64>,
+
+    /// Регулярное выражение для поиска в описании
+    #[arg(long = "description-matches")]
+    description_matches: Option<String>,
+
+    /// Выражение для отбора транзакций, например `type == TRANSFER && amount > 10000`
+    #[arg(long = "where", value_parser = Query::parse)]
+    query: Option<Query>,
+
+    /// Упорядочить транзакции по полю
+    #[arg(long = "sort-by")]
+    sort_by: Option<SortField>,
+
+    /// Сортировать по убыванию
+    #[arg(long, requires = "sort_by")]
+    desc: bool,
+
+    /// Удалить повторы TX_ID
+    #[arg(long)]
+    dedupe: bool,
+
+    /// Что делать с повторами TX_ID, содержимое которых различается
+    #[arg(
+        long = "on-conflict",
+        default_value = "keep-first",
+        requires = "dedupe"
+    )]
+    on_conflict: ConflictPolicy,
+
+    /// Число транзакций, сортируемых в памяти за раз
+    #[arg(long = "sort-run-size", default_value_t = 100_000)]
+    sort_run_size: usize,
+
+    /// Каталог для временных файлов сортировки
+    #[arg(long = "temp-dir")]
+    temp_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Разбить транзакции на файлы по шаблону пути
+    Split(SplitArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct SplitArgs {
+    /// Шаблон пути, например `out/{yyyy}/{mm}/{dd}.bin`
+    #[arg(long)]
+    path: String,
+
+    /// Число корзин для подстановки {bucket}
+    #[arg(long)]
+    buckets: Option<u64>,
+
+    /// Число транзакций в части для подстановки {chunk}
+    #[arg(long = "chunk-size")]
+    chunk_size: Option<u64>,
 }
 
 #[derive(ValueEnum, Clone, Debug)]
@@ -26,24 +181,314 @@ enum FormatType {
     Text,
 }
 
+impl From<FormatType> for FormatKind {
+    fn from(format: FormatType) -> Self {
+        match format {
+            FormatType::Binary => FormatKind::Binary,
+            FormatType::Csv => FormatKind::Csv,
+            FormatType::Text => FormatKind::Text,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CollisionAction {
+    Ignore,
+    Warn,
+    Error,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SortField {
+    TxId,
+    Timestamp,
+    Amount,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ConflictPolicy {
+    KeepFirst,
+    KeepLast,
+    Error,
+}
+
+/// Поток транзакций между этапами конвертации
+type Records = Box<dyn Iterator<Item = ParseResult<Transaction>>>;
+
+/// Имя типа из командной строки; регистр не важен
+fn parse_type(value: &str) -> ParseResult<TransactionType> {
+    TransactionType::from_name(&value.to_uppercase(), UnknownCodePolicy::Preserve)
+}
+
+/// Имя статуса из командной строки; регистр не важен
+fn parse_status(value: &str) -> ParseResult<TransactionStatus> {
+    TransactionStatus::from_name(&value.to_uppercase(), UnknownCodePolicy::Preserve)
+}
+
+/// Собирает фильтр из аргументов командной строки
+fn build_filter(args: &Args) -> ParseResult<TransactionFilter> {
+    let mut filter = TransactionFilter::new()
+        .types(&args.types)
+        .statuses(&args.statuses)
+        .users(&args.users);
+    if let Some(amount) = args.min_amount {
+        filter = filter.min_amount(amount);
+    }
+    if let Some(amount) = args.max_amount {
+        filter = filter.max_amount(amount);
+    }
+    if let Some(timestamp) = args.since {
+        filter = filter.since(timestamp);
+    }
+    if let Some(timestamp) = args.until {
+        filter = filter.until(timestamp);
+    }
+    if let Some(pattern) = &args.description_matches {
+        filter = filter.description_matches(pattern)?;
+    }
+    if let Some(query) = &args.query {
+        filter = filter.query(query.clone());
+    }
+    Ok(filter)
+}
+
+/// Сортировка с настройками из аргументов командной строки
+fn sorter(args: &Args, order: SortOrder) -> ExternalSorter {
+    let sorter = ExternalSorter::new(order).with_run_size(args.sort_run_size);
+    match &args.temp_dir {
+        Some(dir) => sorter.with_temp_dir(dir),
+        None => sorter,
+    }
+}
+
+/// Сортирует поток и собирает по нему колонки CSV
+///
+/// Сортировка читает весь вход до того, как отдаст первую запись, поэтому
+/// к началу записи колонки известны и CSV можно писать потоком.
+fn sort_collecting(
+    args: &Args,
+    order: SortOrder,
+    records: Records,
+    columns: &mut Option<CsvColumns>,
+) -> ParseResult<Records> {
+    let mut seen = CsvColumns::new();
+    let sorted = sorter(args, order).sort(records.inspect(|record| {
+        if let Ok(transaction) = record {
+            seen.add(transaction);
+        }
+    }))?;
+    *columns = Some(seen);
+    Ok(Box::new(sorted))
+}
+
+/// Удаляет повторы и упорядочивает поток
+///
+/// Для поиска повторов поток сортируется по TX_ID, поэтому при `--dedupe`
+/// без `--sort-by` транзакции выводятся в порядке TX_ID. Вместе с потоком
+/// возвращаются колонки CSV, если поток сортировался; после удаления
+/// повторов среди них могут остаться колонки без значений.
+fn arrange(args: &Args, mut records: Records) -> ParseResult<(Records, Option<CsvColumns>)> {
+    let by_tx_id = SortOrder::ascending(SortKey::TxId);
+    let mut columns = None;
+
+    if args.dedupe {
+        let policy = match args.on_conflict {
+            ConflictPolicy::KeepFirst => DuplicatePolicy::KeepFirst,
+            ConflictPolicy::KeepLast => DuplicatePolicy::KeepLast,
+            ConflictPolicy::Error => DuplicatePolicy::Error,
+        };
+        let sorted = sort_collecting(args, by_tx_id, records, &mut columns)?;
+        records = Box::new(Deduplicated::new(sorted, policy));
+    }
+
+    if let Some(field) = args.sort_by {
+        let key = match field {
+            SortField::TxId => SortKey::TxId,
+            SortField::Timestamp => SortKey::Timestamp,
+            SortField::Amount => SortKey::Amount,
+        };
+        let order = SortOrder {
+            key,
+            descending: args.desc,
+        };
+        if !(args.dedupe && order == by_tx_id) {
+            records = sort_collecting(args, order, records, &mut columns)?;
+        }
+    }
+
+    Ok((records, columns))
+}
+
+/// Отбирает транзакции фильтром, удаляет повторы и упорядочивает поток
+fn process(
+    args: &Args,
+    filter: TransactionFilter,
+    records: Records,
+) -> ParseResult<(Records, Option<CsvColumns>)> {
+    let records = Box::new(records.filter(move |record| match record {
+        Ok(transaction) => filter.matches(transaction),
+        Err(_) => true,
+    }));
+    arrange(args, records)
+}
+
+/// Конвертирует каждый распознанный файл `input_dir` в `output_dir`
+fn convert_dir(
+    args: &Args,
+    filter: &TransactionFilter,
+    input: InputOptions,
+    output: OutputOptions,
+    input_dir: &Path,
+    output_dir: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.command.is_some() {
+        return Err("split не используется вместе с --input-dir".into());
+    }
+    let format = match args.input_format.as_slice() {
+        [] => None,
+        [format] => Some(format.clone().into()),
+        _ => return Err("С --input-dir указывается не больше одного --input-format".into()),
+    };
+    let workers = match args.jobs {
+        Some(jobs) => jobs,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let started = Instant::now();
+    let plan = batch::plan(input_dir, output_dir, format, output.format)?;
+    let outcomes = batch::run(&plan.jobs, workers, |job| {
+        if let Some(parent) = job.output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let records = inputs::open(&job.input, input)?;
+        let (records, columns) = process(args, filter.clone(), records)?;
+        write_file(&job.output, output, columns.as_ref(), records)
+    });
+
+    let failed = batch::report(&plan, &outcomes, started.elapsed());
+    if failed > 0 {
+        return Err(format!("Не удалось сконвертировать файлов: {failed}").into());
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
+    let filter = build_filter(&args)?;
+    let writes_files = args.output.is_some() || args.output_dir.is_some() || args.command.is_some();
+    if args.verify && !writes_files {
+        return Err("--verify проверяет записанный файл, укажите --output".into());
+    }
+    if args.index && !writes_files {
+        return Err("--index строит индекс записанного файла, укажите --output".into());
+    }
 
-    let input_file = File::open(&args.input)?;
-    let reader = BufReader::new(input_file);
+    let input_options = InputOptions {
+        unknown_codes: if args.keep_unknown_codes {
+            UnknownCodePolicy::Preserve
+        } else {
+            UnknownCodePolicy::Reject
+        },
+        trailer: if args.require_trailer {
+            TrailerPolicy::Require
+        } else {
+            TrailerPolicy::Optional
+        },
+    };
+    let output_options = OutputOptions {
+        format: args.output_format.clone().into(),
+        trailer: args.trailer,
+        header: args.binary_header,
+        index: args.index,
+        verify: args.verify,
+    };
+    if output_options.format != FormatKind::Binary {
+        if output_options.header {
+            return Err("--binary-header применим только к --output-format binary".into());
+        }
+        if output_options.index {
+            return Err("--index применим только к --output-format binary".into());
+        }
+    }
+    if let (Some(input_dir), Some(output_dir)) = (&args.input_dir, &args.output_dir) {
+        return convert_dir(
+            &args,
+            &filter,
+            input_options,
+            output_options,
+            input_dir,
+            output_dir,
+        );
+    }
+
+    let formats: Vec<FormatKind> = args.input_format.iter().cloned().map(Into::into).collect();
+    let patterns = if args.input.is_empty() {
+        vec![inputs::STDIN.to_string()]
+    } else {
+        args.input.clone()
+    };
+    let inputs = inputs::resolve(&patterns, &formats)?;
 
-    let transactions = match args.input_format {
-        FormatType::Binary => BinaryFormat.read_from(reader)?,
-        FormatType::Csv => CsvFormat.read_from(reader)?,
-        FormatType::Text => TextFormat.read_from(reader)?,
+    let policy = match args.on_collision {
+        CollisionAction::Ignore => CollisionPolicy::Ignore,
+        CollisionAction::Warn => CollisionPolicy::Warn,
+        CollisionAction::Error => CollisionPolicy::Error,
     };
+    let check = Rc::new(RefCell::new(CollisionCheck::new(&inputs, policy)));
+    let mut sources = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        let records = inputs::open(input, input_options)?;
+        sources.push(if inputs.len() > 1 && policy != CollisionPolicy::Ignore {
+            CollisionCheck::watch(&check, index, records)
+        } else {
+            records
+        });
+    }
+
+    let records: Records = if args.merge_sorted {
+        Box::new(MergedRecords::new(
+            sources,
+            SortOrder::ascending(SortKey::Timestamp),
+        ))
+    } else {
+        Box::new(sources.into_iter().flatten())
+    };
+    let (records, columns) = process(&args, filter, records)?;
+
+    if let Some(Command::Split(split_args)) = &args.command {
+        if args.output.is_some() {
+            return Err(
+                "split записывает файлы по шаблону --path, --output не используется".into(),
+            );
+        }
+        let template =
+            PathTemplate::parse(&split_args.path, split_args.buckets, split_args.chunk_size)?;
+        let mut splitter = Splitter::new(template, output_options)?;
+        for transaction in records {
+            splitter.push(&transaction?)?;
+        }
+        for (path, count) in splitter.finish()? {
+            println!("{path}\t{count}");
+        }
+        return Ok(());
+    }
 
-    let stdout = io::stdout();
-    let writer = BufWriter::new(stdout);
-    match args.output_format {
-        FormatType::Binary => BinaryFormat.write_to(writer, &transactions)?,
-        FormatType::Csv => CsvFormat.write_to(writer, &transactions)?,
-        FormatType::Text => TextFormat.write_to(writer, &transactions)?,
+    match &args.output {
+        Some(path) => {
+            write_file(path, output_options, columns.as_ref(), records)?;
+        }
+        None => {
+            let stdout = io::stdout();
+            if output_options.format == FormatKind::Binary && stdout.is_terminal() {
+                return Err(
+                    "Бинарные данные не выводятся в терминал: укажите --output или перенаправьте вывод"
+                        .into(),
+                );
+            }
+            let mut writer = BufWriter::new(stdout);
+            write_records(output_options, columns.as_ref(), &mut writer, records)?;
+            writer.flush()?;
+        }
     }
 
     Ok(())
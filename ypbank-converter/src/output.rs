@@ -0,0 +1,261 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+use ypbank_parser::{
+    BinaryFormat, BinaryIndex, CsvColumns, CsvFormat, FileHeader, Format, FormatKind, ParseResult,
+    TextFormat, TrailerPolicy, Transaction, UnknownCodePolicy,
+};
+
+/// Производитель в заголовке бинарных файлов
+const PRODUCER: &str = concat!("ypbank-converter ", env!("CARGO_PKG_VERSION"));
+
+/// Как записывать выходные данные
+#[derive(Debug, Clone, Copy)]
+pub struct OutputOptions {
+    pub format: FormatKind,
+    /// Дописывать итог с числом записей и суммой
+    pub trailer: bool,
+    /// Начинать бинарный файл с заголовка
+    pub header: bool,
+    /// Строить индекс бинарного файла рядом с ним
+    pub index: bool,
+    /// Проверять записанный файл чтением, см. [`write_file`]
+    pub verify: bool,
+}
+
+/// Записывает поток транзакций в указанном формате
+///
+/// CSV без `columns` собирается в памяти, чтобы определить набор колонок;
+/// с известными заранее колонками он пишется потоком.
+pub fn write_records<W, I>(
+    options: OutputOptions,
+    columns: Option<&CsvColumns>,
+    writer: W,
+    records: I,
+) -> ParseResult<()>
+where
+    W: Write,
+    I: IntoIterator<Item = ParseResult<Transaction>>,
+{
+    let trailer = options.trailer;
+    match options.format {
+        FormatKind::Binary => {
+            let mut format = BinaryFormat::new().with_trailer(trailer);
+            if options.header {
+                format = format.with_header(FileHeader::new(PRODUCER));
+            }
+            format.write_records(writer, records)
+        }
+        FormatKind::Csv => {
+            let format = CsvFormat::new().with_trailer(trailer);
+            match columns {
+                Some(columns) => format.write_records_with_columns(writer, columns, records),
+                None => format.write_records(writer, records),
+            }
+        }
+        FormatKind::Text => TextFormat::new()
+            .with_trailer(trailer)
+            .write_records(writer, records),
+    }
+}
+
+/// Читает записанный файл обратно, сохраняя неизвестные коды
+///
+/// Если итог записывался, он обязателен и сверяется при чтении.
+fn read_back<R: io::Read + 'static>(
+    options: OutputOptions,
+    reader: R,
+) -> Box<dyn Iterator<Item = ParseResult<Transaction>>> {
+    let codes = UnknownCodePolicy::Preserve;
+    let trailer = if options.trailer {
+        TrailerPolicy::Require
+    } else {
+        TrailerPolicy::Optional
+    };
+    match options.format {
+        FormatKind::Binary => Box::new(
+            BinaryFormat::new()
+                .with_unknown_codes(codes)
+                .with_trailer_policy(trailer)
+                .records(reader),
+        ),
+        FormatKind::Csv => Box::new(
+            CsvFormat::new()
+                .with_unknown_codes(codes)
+                .with_trailer_policy(trailer)
+                .records(reader),
+        ),
+        FormatKind::Text => Box::new(
+            TextFormat::new()
+                .with_unknown_codes(codes)
+                .with_trailer_policy(trailer)
+                .records(reader),
+        ),
+    }
+}
+
+/// Записывает поток в файл, который появляется только при успехе
+///
+/// С `options.verify` записанный файл до переименования читается обратно и
+/// сравнивается с исходными транзакциями; для этого они по ходу записи
+/// сохраняются во временный файл в формате YPBankBin. При расхождении
+/// итоговый файл не создаётся. С `options.index` после записи строится
+/// индекс, который сохраняется рядом в файл `<путь>.idx`. `columns` — как
+/// у [`write_records`]. Возвращает число записанных транзакций.
+pub fn write_file<I>(
+    path: &Path,
+    options: OutputOptions,
+    columns: Option<&CsvColumns>,
+    records: I,
+) -> Result<u64, Box<dyn Error>>
+where
+    I: IntoIterator<Item = ParseResult<Transaction>>,
+{
+    let spool_format = BinaryFormat::new().with_unknown_codes(UnknownCodePolicy::Preserve);
+    let mut spool = if options.verify {
+        Some(BufWriter::new(tempfile::tempfile()?))
+    } else {
+        None
+    };
+
+    let mut count = 0;
+    let mut file = AtomicFile::create(path)?;
+    let mut writer = BufWriter::new(&mut file);
+    let records = records.into_iter().map(|record| {
+        let transaction = record?;
+        if let Some(spool) = &mut spool {
+            spool_format.write_to(spool, std::slice::from_ref(&transaction))?;
+        }
+        count += 1;
+        Ok(transaction)
+    });
+    write_records(options, columns, &mut writer, records)?;
+    writer.flush()?;
+    drop(writer);
+
+    if let Some(spool) = spool {
+        let mut spool = spool.into_inner().map_err(|e| e.into_error())?;
+        spool.rewind()?;
+        let expected = spool_format.records(BufReader::new(spool));
+        let actual = read_back(options, BufReader::new(file.reopen()?));
+        verify_records(path, expected, actual)?;
+    }
+
+    let index = if options.index {
+        Some(BinaryIndex::build(BufReader::new(file.reopen()?))?)
+    } else {
+        None
+    };
+
+    file.commit()?;
+    if let Some(index) = index {
+        let mut sidecar = AtomicFile::create(&BinaryIndex::sidecar_path(path))?;
+        let mut writer = BufWriter::new(&mut sidecar);
+        index.write_to(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        sidecar.commit()?;
+    }
+    Ok(count)
+}
+
+/// Сравнивает записанные транзакции с прочитанными обратно
+fn verify_records(
+    path: &Path,
+    expected: impl Iterator<Item = ParseResult<Transaction>>,
+    mut actual: impl Iterator<Item = ParseResult<Transaction>>,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.display();
+    let mut written = 0;
+    for expected in expected {
+        let expected = expected?;
+        written += 1;
+        match actual.next() {
+            Some(Ok(actual)) if actual == expected => {}
+            Some(Ok(actual)) => {
+                return Err(format!(
+                    "Проверка {path} не пройдена: запись № {written} отличается: \
+                     записано {expected:?}, прочитано {actual:?}"
+                )
+                .into());
+            }
+            Some(Err(e)) => {
+                return Err(format!(
+                    "Проверка {path} не пройдена: запись № {written} не читается: {e}"
+                )
+                .into());
+            }
+            None => {
+                return Err(format!(
+                    "Проверка {path} не пройдена: прочитано записей: {}, записано больше",
+                    written - 1
+                )
+                .into());
+            }
+        }
+    }
+    if actual.next().is_some() {
+        return Err(format!(
+            "Проверка {path} не пройдена: записано записей: {written}, прочитано больше"
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Файл, который появляется по своему пути только после [`AtomicFile::commit`]
+///
+/// Данные пишутся во временный файл в том же каталоге и затем
+/// переименовываются на место итогового. Если до `commit` произошла
+/// ошибка, временный файл удаляется, а прежнее содержимое итогового
+/// файла остаётся нетронутым.
+pub struct AtomicFile {
+    temp: NamedTempFile,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(".ypbank-");
+        // Права как у File::create: 0o666 с учётом umask, а не 0o600
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(std::fs::Permissions::from_mode(0o666));
+        }
+
+        Ok(AtomicFile {
+            temp: builder.tempfile_in(dir)?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Открывает записанные данные для чтения с начала
+    pub fn reopen(&self) -> io::Result<File> {
+        self.temp.reopen()
+    }
+
+    /// Сбрасывает данные на диск и переименовывает файл на место итогового
+    pub fn commit(self) -> io::Result<File> {
+        self.temp.as_file().sync_all()?;
+        self.temp.persist(&self.path).map_err(|e| e.error)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.temp.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.temp.flush()
+    }
+}
@@ -0,0 +1,146 @@
+use crate::output::{self, OutputOptions};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use ypbank_parser::{
+    BinaryFormat, CsvColumns, Format, ParseResult, PathTemplate, Transaction, UnknownCodePolicy,
+};
+
+/// Наибольшее число одновременно открытых промежуточных файлов
+const MAX_OPEN_FILES: usize = 256;
+
+/// Часть, в которую собираются транзакции с одним путём
+struct Partition {
+    spool: PathBuf,
+    writer: Option<BufWriter<File>>,
+    last_used: u64,
+    records: u64,
+    /// Колонки CSV, чтобы записать итоговый файл потоком
+    columns: CsvColumns,
+}
+
+/// Разбиение потока транзакций на файлы по шаблону пути
+///
+/// Транзакции сначала дописываются в промежуточные файлы в формате
+/// YPBankBin во временном каталоге, а итоговые файлы создаются только
+/// после того, как весь входной поток прочитан без ошибок. Чтобы не
+/// упереться в ограничение на число открытых файлов, давно не
+/// использованные промежуточные файлы закрываются и при необходимости
+/// открываются снова для дозаписи.
+pub struct Splitter {
+    template: PathTemplate,
+    output: OutputOptions,
+    spool_dir: TempDir,
+    partitions: HashMap<String, Partition>,
+    /// Пути в порядке появления, чтобы итоговые файлы писались предсказуемо
+    paths: Vec<String>,
+    open_files: usize,
+    clock: u64,
+}
+
+impl Splitter {
+    pub fn new(template: PathTemplate, output: OutputOptions) -> ParseResult<Self> {
+        Ok(Splitter {
+            template,
+            output,
+            spool_dir: tempfile::tempdir()?,
+            partitions: HashMap::new(),
+            paths: Vec::new(),
+            open_files: 0,
+            clock: 0,
+        })
+    }
+
+    /// Добавляет транзакцию в её часть
+    pub fn push(&mut self, transaction: &Transaction) -> ParseResult<()> {
+        let path = self.template.render(transaction);
+        self.clock += 1;
+
+        let needs_open = self
+            .partitions
+            .get(&path)
+            .is_none_or(|partition| partition.writer.is_none());
+        if needs_open && self.open_files >= MAX_OPEN_FILES {
+            self.close_least_recent()?;
+        }
+
+        let index = self.partitions.len();
+        let partition = match self.partitions.entry(path) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.paths.push(entry.key().clone());
+                entry.insert(Partition {
+                    spool: self.spool_dir.path().join(format!("{index}.bin")),
+                    writer: None,
+                    last_used: 0,
+                    records: 0,
+                    columns: CsvColumns::new(),
+                })
+            }
+        };
+        partition.last_used = self.clock;
+        partition.records += 1;
+        partition.columns.add(transaction);
+
+        let writer = match &mut partition.writer {
+            Some(writer) => writer,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&partition.spool)?;
+                self.open_files += 1;
+                partition.writer.insert(BufWriter::new(file))
+            }
+        };
+        spool_format().write_to(writer, std::slice::from_ref(transaction))
+    }
+
+    /// Закрывает промежуточный файл, который дольше всех не использовался
+    fn close_least_recent(&mut self) -> ParseResult<()> {
+        let least_recent = self
+            .partitions
+            .values_mut()
+            .filter(|partition| partition.writer.is_some())
+            .min_by_key(|partition| partition.last_used);
+        if let Some(partition) = least_recent {
+            if let Some(mut writer) = partition.writer.take() {
+                writer.flush()?;
+            }
+            self.open_files -= 1;
+        }
+        Ok(())
+    }
+
+    /// Записывает итоговые файлы и возвращает их пути с числом транзакций
+    pub fn finish(mut self) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
+        for partition in self.partitions.values_mut() {
+            if let Some(mut writer) = partition.writer.take() {
+                writer.flush()?;
+            }
+        }
+
+        let mut written = Vec::new();
+        for name in &self.paths {
+            let partition = &self.partitions[name];
+            let spool = BufReader::new(File::open(&partition.spool)?);
+            let path = Path::new(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let records = spool_format().records(spool);
+            output::write_file(path, self.output, Some(&partition.columns), records)?;
+            written.push((name.clone(), partition.records));
+        }
+        Ok(written)
+    }
+}
+
+/// Формат промежуточных файлов; неизвестные коды сохраняются без изменений
+fn spool_format() -> BinaryFormat {
+    BinaryFormat::new().with_unknown_codes(UnknownCodePolicy::Preserve)
+}
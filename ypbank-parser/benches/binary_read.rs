@@ -0,0 +1,85 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::fs::File;
+use std::hint::black_box;
+use std::io::{BufReader, BufWriter, Write};
+use ypbank_parser::{BinaryFormat, Format, MappedFile, ParseResult, Transaction};
+
+const RECORDS: u64 = 100_000;
+
+/// Файл с транзакциями, похожими на выгрузку за день
+fn archive() -> ParseResult<tempfile::NamedTempFile> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    let records = (0..RECORDS).map(|i| {
+        Transaction::transfer(i % 1000 + 1, i % 977 + 1, (i % 50_000 + 1) as i64)
+            .tx_id(i + 1)
+            .timestamp(1633036800000 + i * 1000)
+            .description(format!("Перевод по договору №{i}"))
+            .build()
+    });
+    let mut writer = BufWriter::new(&mut file);
+    BinaryFormat::new().write_records(&mut writer, records)?;
+    writer.flush()?;
+    drop(writer);
+    Ok(file)
+}
+
+/// Сумма переводов: типичный аналитический проход без описаний
+fn bench_scan(c: &mut Criterion) {
+    let file = archive().expect("не удалось подготовить файл");
+    let format = BinaryFormat::new();
+
+    let mut group = c.benchmark_group("binary_scan");
+    group.throughput(Throughput::Elements(RECORDS));
+
+    group.bench_function("read", |b| {
+        b.iter(|| {
+            let reader = BufReader::new(File::open(file.path()).unwrap());
+            let total: i64 = format
+                .records(reader)
+                .map(|transaction| transaction.unwrap().amount)
+                .sum();
+            black_box(total)
+        })
+    });
+
+    group.bench_function("mmap", |b| {
+        b.iter(|| {
+            // SAFETY: временный файл бенчмарка не меняется во время замеров
+            let mapped = unsafe { MappedFile::open(file.path()) }.unwrap();
+            let total: i64 = mapped
+                .records(&format)
+                .map(|transaction| transaction.unwrap().amount)
+                .sum();
+            black_box(total)
+        })
+    });
+
+    group.bench_function("mmap_description", |b| {
+        b.iter(|| {
+            // SAFETY: временный файл бенчмарка не меняется во время замеров
+            let mapped = unsafe { MappedFile::open(file.path()) }.unwrap();
+            let total: usize = mapped
+                .records(&format)
+                .map(|transaction| transaction.unwrap().description().unwrap().len())
+                .sum();
+            black_box(total)
+        })
+    });
+
+    group.bench_function("mmap_to_transaction", |b| {
+        b.iter(|| {
+            // SAFETY: временный файл бенчмарка не меняется во время замеров
+            let mapped = unsafe { MappedFile::open(file.path()) }.unwrap();
+            let total: i64 = mapped
+                .records(&format)
+                .map(|transaction| transaction.unwrap().to_transaction().unwrap().amount)
+                .sum();
+            black_box(total)
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::path::Path;
+use std::thread;
+use ypbank_parser::{BinaryFormat, CsvFormat, Format};
+
+/// Образцы из корня репозитория и они же, повторённые `SCALE` раз
+const SCALE: usize = 32;
+
+fn sample(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()))
+}
+
+/// Число потоков: 1, 4 и все доступные
+fn thread_counts() -> Vec<usize> {
+    let available = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut counts = vec![1, 4, available];
+    counts.sort();
+    counts.dedup();
+    counts
+}
+
+fn bench_binary(c: &mut Criterion) {
+    let format = BinaryFormat::new();
+    let one = sample("transactions.bin");
+    let scaled = one.repeat(SCALE);
+
+    let mut group = c.benchmark_group("parallel_binary");
+    for (label, data) in [("sample", &one), ("sample_x32", &scaled)] {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::new("read_from", label), data, |b, data| {
+            b.iter(|| black_box(format.read_from(data.as_slice()).unwrap()))
+        });
+        for threads in thread_counts() {
+            let id = BenchmarkId::new(format!("read_parallel_{threads}"), label);
+            group.bench_with_input(id, data, |b, data| {
+                b.iter(|| black_box(format.read_parallel(data, threads).unwrap()))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_csv(c: &mut Criterion) {
+    let format = CsvFormat::new();
+    let one = sample("transactions.csv");
+    // Заголовок оставляем только в начале
+    let header_len = one
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(0, |end| end + 1);
+    let mut scaled = one.clone();
+    for _ in 1..SCALE {
+        scaled.extend_from_slice(&one[header_len..]);
+    }
+
+    let mut group = c.benchmark_group("parallel_csv");
+    for (label, data) in [("sample", &one), ("sample_x32", &scaled)] {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::new("read_from", label), data, |b, data| {
+            b.iter(|| black_box(format.read_from(data.as_slice()).unwrap()))
+        });
+        for threads in thread_counts() {
+            let id = BenchmarkId::new(format!("read_parallel_{threads}"), label);
+            group.bench_with_input(id, data, |b, data| {
+                b.iter(|| black_box(format.read_parallel(data, threads).unwrap()))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_binary, bench_csv);
+criterion_main!(benches);
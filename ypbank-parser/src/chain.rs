@@ -0,0 +1,110 @@
+use crate::Transaction;
+use std::collections::{HashMap, HashSet};
+
+/// Собирает цепочку связанных транзакций, в которую входит `tx_id`
+///
+/// От указанной транзакции поиск поднимается по `parent_tx_id` до исходной
+/// операции, затем собирает все её возвраты и сторно на любой глубине.
+/// Результат упорядочен по времени, а при равном времени — по `tx_id`.
+/// Если `tx_id` нет в наборе, возвращается пустой список.
+pub fn related_chain(transactions: &[Transaction], tx_id: u64) -> Vec<&Transaction> {
+    let by_id: HashMap<u64, &Transaction> = transactions.iter().map(|t| (t.tx_id, t)).collect();
+
+    let Some(mut root) = by_id.get(&tx_id).copied() else {
+        return Vec::new();
+    };
+
+    // Защита от циклов в некорректных данных
+    let mut visited = HashSet::from([root.tx_id]);
+    while let Some(parent) = root.parent_tx_id.and_then(|id| by_id.get(&id)) {
+        if !visited.insert(parent.tx_id) {
+            break;
+        }
+        root = parent;
+    }
+
+    let mut children: HashMap<u64, Vec<&Transaction>> = HashMap::new();
+    for transaction in transactions {
+        if let Some(parent_tx_id) = transaction.parent_tx_id {
+            children.entry(parent_tx_id).or_default().push(transaction);
+        }
+    }
+
+    let mut chain = vec![root];
+    let mut seen = HashSet::from([root.tx_id]);
+    let mut next = 0;
+    while let Some(current) = chain.get(next).copied() {
+        next += 1;
+        for child in children.get(&current.tx_id).into_iter().flatten() {
+            if seen.insert(child.tx_id) {
+                chain.push(child);
+            }
+        }
+    }
+
+    chain.sort_by_key(|t| (t.timestamp, t.tx_id));
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseResult, TransactionType};
+
+    fn refund(tx_id: u64, parent_tx_id: u64, timestamp: u64) -> ParseResult<Transaction> {
+        Transaction::builder()
+            .tx_id(tx_id)
+            .tx_type(TransactionType::Refund)
+            .from_user_id(2)
+            .to_user_id(1)
+            .amount(10)
+            .timestamp(timestamp)
+            .parent_tx_id(parent_tx_id)
+            .build()
+    }
+
+    #[test]
+    fn test_related_chain() -> ParseResult<()> {
+        let transactions = vec![
+            refund(3, 1, 300)?,
+            Transaction::transfer(1, 2, 100)
+                .tx_id(1)
+                .timestamp(100)
+                .build()?,
+            Transaction::deposit(1, 50)
+                .tx_id(2)
+                .timestamp(200)
+                .build()?,
+            refund(4, 3, 400)?,
+        ];
+
+        let ids: Vec<u64> = related_chain(&transactions, 4)
+            .iter()
+            .map(|t| t.tx_id)
+            .collect();
+        assert_eq!(ids, vec![1, 3, 4]);
+
+        let ids: Vec<u64> = related_chain(&transactions, 2)
+            .iter()
+            .map(|t| t.tx_id)
+            .collect();
+        assert_eq!(ids, vec![2]);
+
+        assert!(related_chain(&transactions, 99).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_related_chain_cycle() -> ParseResult<()> {
+        let transactions = vec![refund(1, 2, 100)?, refund(2, 1, 200)?];
+
+        let ids: Vec<u64> = related_chain(&transactions, 1)
+            .iter()
+            .map(|t| t.tx_id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        Ok(())
+    }
+}
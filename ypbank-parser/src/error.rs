@@ -1,3 +1,4 @@
+use crate::Totals;
 use thiserror::Error;
 
 /// Тип результата для операций парсинга
@@ -12,6 +13,9 @@ pub enum ParseError {
     #[error("UTF-8 ошибка: {0}")]
     Utf8(#[from] std::string::FromUtf8Error),
 
+    #[error("UTF-8 ошибка: {0}")]
+    Utf8Str(#[from] std::str::Utf8Error),
+
     #[error("Ошибка парсинга числа: {0}")]
     ParseInt(#[from] std::num::ParseIntError),
 
@@ -30,6 +34,55 @@ pub enum ParseError {
     #[error("Размер записи не совпадает")]
     RecordSizeMismatch,
 
+    #[error("Размер записи {size} байт превышает предел {max} байт")]
+    FrameTooLarge { size: usize, max: usize },
+
     #[error("Поле не найдено: {0}")]
     MissingField(String),
+
+    #[error("Некорректная транзакция: {0}")]
+    InvalidTransaction(String),
+
+    #[error("Переполнение баланса пользователя {user_id} в транзакции {tx_id}")]
+    BalanceOverflow { user_id: u64, tx_id: u64 },
+
+    #[error("Некорректный фильтр: {0}")]
+    InvalidFilter(String),
+
+    #[error("Ошибка в запросе, позиция {position}: {message}")]
+    InvalidQuery { position: usize, message: String },
+
+    #[error("Транзакции с TX_ID {0} различаются")]
+    DuplicateTxId(u64),
+
+    #[error("Транзакция {0} нарушает порядок сортировки входных данных")]
+    UnsortedInput(u64),
+
+    #[error("Некорректный шаблон пути: {0}")]
+    InvalidTemplate(String),
+
+    #[error("TX_ID {tx_id} встречается в {first} и {second}")]
+    TxIdCollision {
+        tx_id: u64,
+        first: String,
+        second: String,
+    },
+
+    #[error(
+        "Контрольный итог не сходится: в итоге {} записей на сумму {}, прочитано {} на сумму {}",
+        expected.count,
+        expected.amount,
+        actual.count,
+        actual.amount
+    )]
+    TrailerMismatch { expected: Totals, actual: Totals },
+
+    #[error("Нет итоговой записи, данные могут быть обрезаны")]
+    MissingTrailer,
+
+    #[error("Число записей не совпадает с заголовком: в заголовке {expected}, прочитано {actual}")]
+    RecordCountMismatch { expected: u64, actual: u64 },
+
+    #[error("Индекс не соответствует файлу данных, постройте его заново")]
+    StaleIndex,
 }
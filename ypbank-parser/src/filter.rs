@@ -0,0 +1,215 @@
+use crate::{ParseError, ParseResult, Query, Transaction, TransactionStatus, TransactionType};
+use regex::Regex;
+
+/// Условие отбора транзакций
+///
+/// Условия объединяются через «и»: транзакция подходит, если проходит
+/// все заданные проверки. Пустой фильтр пропускает любую транзакцию.
+/// Списки типов, статусов и пользователей проверяются через «или»:
+/// достаточно совпадения с одним из значений. Границы суммы и времени
+/// включаются в диапазон.
+#[derive(Debug, Default, Clone)]
+pub struct TransactionFilter {
+    types: Vec<TransactionType>,
+    statuses: Vec<TransactionStatus>,
+    users: Vec<u64>,
+    min_amount: Option<i64>,
+    max_amount: Option<i64>,
+    since: Option<u64>,
+    until: Option<u64>,
+    description: Option<Regex>,
+    query: Option<Query>,
+}
+
+impl TransactionFilter {
+    /// Создаёт фильтр, который пропускает все транзакции
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Оставляет транзакции одного из указанных типов
+    pub fn types(mut self, types: &[TransactionType]) -> Self {
+        self.types = types.to_vec();
+        self
+    }
+
+    /// Оставляет транзакции с одним из указанных статусов
+    pub fn statuses(mut self, statuses: &[TransactionStatus]) -> Self {
+        self.statuses = statuses.to_vec();
+        self
+    }
+
+    /// Оставляет транзакции, где один из пользователей — отправитель или получатель
+    pub fn users(mut self, users: &[u64]) -> Self {
+        self.users = users.to_vec();
+        self
+    }
+
+    pub fn min_amount(mut self, amount: i64) -> Self {
+        self.min_amount = Some(amount);
+        self
+    }
+
+    pub fn max_amount(mut self, amount: i64) -> Self {
+        self.max_amount = Some(amount);
+        self
+    }
+
+    /// Оставляет транзакции с `TIMESTAMP` не раньше указанного
+    pub fn since(mut self, timestamp: u64) -> Self {
+        self.since = Some(timestamp);
+        self
+    }
+
+    /// Оставляет транзакции с `TIMESTAMP` не позже указанного
+    pub fn until(mut self, timestamp: u64) -> Self {
+        self.until = Some(timestamp);
+        self
+    }
+
+    /// Оставляет транзакции, описание которых содержит совпадение с регулярным выражением
+    pub fn description_matches(mut self, pattern: &str) -> ParseResult<Self> {
+        let regex = Regex::new(pattern).map_err(|e| ParseError::InvalidFilter(e.to_string()))?;
+        self.description = Some(regex);
+        Ok(self)
+    }
+
+    /// Оставляет транзакции, удовлетворяющие выражению [`Query`]
+    pub fn query(mut self, query: Query) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    /// Проверяет, проходит ли транзакция фильтр
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        (self.types.is_empty() || self.types.contains(&transaction.tx_type))
+            && (self.statuses.is_empty() || self.statuses.contains(&transaction.status))
+            && (self.users.is_empty()
+                || self.users.contains(&transaction.from_user_id)
+                || self.users.contains(&transaction.to_user_id))
+            && self.min_amount.is_none_or(|min| transaction.amount >= min)
+            && self.max_amount.is_none_or(|max| transaction.amount <= max)
+            && self
+                .since
+                .is_none_or(|since| transaction.timestamp >= since)
+            && self
+                .until
+                .is_none_or(|until| transaction.timestamp <= until)
+            && self
+                .description
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&transaction.description))
+            && self
+                .query
+                .as_ref()
+                .is_none_or(|query| query.matches(transaction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ParseResult<Vec<Transaction>> {
+        Ok(vec![
+            Transaction::deposit(1, 100)
+                .tx_id(1)
+                .timestamp(100)
+                .description("Salary")
+                .build()?,
+            Transaction::transfer(1, 2, 500)
+                .tx_id(2)
+                .timestamp(200)
+                .status(TransactionStatus::Failure)
+                .description("Rent for May")
+                .build()?,
+            Transaction::withdrawal(2, 50)
+                .tx_id(3)
+                .timestamp(300)
+                .description("ATM")
+                .build()?,
+        ])
+    }
+
+    fn ids(filter: &TransactionFilter, transactions: &[Transaction]) -> Vec<u64> {
+        transactions
+            .iter()
+            .filter(|t| filter.matches(t))
+            .map(|t| t.tx_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_conditions() -> ParseResult<()> {
+        let transactions = sample()?;
+
+        assert_eq!(ids(&TransactionFilter::new(), &transactions), vec![1, 2, 3]);
+        assert_eq!(
+            ids(
+                &TransactionFilter::new()
+                    .types(&[TransactionType::Deposit, TransactionType::Withdrawal]),
+                &transactions
+            ),
+            vec![1, 3]
+        );
+        assert_eq!(
+            ids(
+                &TransactionFilter::new().statuses(&[TransactionStatus::Failure]),
+                &transactions
+            ),
+            vec![2]
+        );
+        assert_eq!(
+            ids(&TransactionFilter::new().users(&[2]), &transactions),
+            vec![2, 3]
+        );
+        assert_eq!(
+            ids(
+                &TransactionFilter::new().min_amount(100).max_amount(500),
+                &transactions
+            ),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids(
+                &TransactionFilter::new().since(200).until(300),
+                &transactions
+            ),
+            vec![2, 3]
+        );
+        assert_eq!(
+            ids(
+                &TransactionFilter::new().description_matches("(?i)^rent")?,
+                &transactions
+            ),
+            vec![2]
+        );
+        assert_eq!(
+            ids(
+                &TransactionFilter::new()
+                    .users(&[1])
+                    .statuses(&[TransactionStatus::Success]),
+                &transactions
+            ),
+            vec![1]
+        );
+
+        assert_eq!(
+            ids(
+                &TransactionFilter::new()
+                    .users(&[2])
+                    .query("amount < 100 || type == TRANSFER".parse()?),
+                &transactions
+            ),
+            vec![2, 3]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_invalid_regex() {
+        let result = TransactionFilter::new().description_matches("(unclosed");
+        assert!(matches!(result, Err(ParseError::InvalidFilter(_))));
+    }
+}
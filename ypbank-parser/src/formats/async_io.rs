@@ -0,0 +1,303 @@
+use super::binary;
+use super::push::{PushParser, Queue};
+use super::text::{self, TextFormat};
+use super::trailer::Totals;
+use super::{
+    BinaryFormat, BinaryPushParser, CsvFormat, CsvPushParser, FileHeader, Format, TextPushParser,
+};
+use crate::{ParseResult, Transaction};
+use futures_core::Stream;
+use std::future::{Future, poll_fn};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// Сколько байт копить перед записью в приёмник
+const WRITE_BUFFER: usize = 64 * 1024;
+/// Сколько байт читать из источника за раз
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Асинхронные чтение и запись поверх tokio (фича `async`)
+///
+/// Разбор записей общий с [`Format`]: асинхронные читатели передают
+/// прочитанные байты тому же [`PushParser`], поэтому ошибки и проверки
+/// итога те же, что и при синхронном чтении.
+pub trait AsyncFormat: Format {
+    /// Поток транзакций из асинхронного источника
+    type AsyncRecords<R: AsyncRead + Unpin>: Stream<Item = ParseResult<Transaction>> + Unpin;
+
+    /// Читает транзакции потоком. После первой ошибки поток завершается.
+    fn records_async<R: AsyncRead + Unpin>(&self, reader: R) -> Self::AsyncRecords<R>;
+
+    /// Записывает транзакции по мере поступления из потока. При первой
+    /// ошибке в потоке запись прекращается и ошибка возвращается.
+    fn write_records_async<W, S>(
+        &self,
+        writer: W,
+        records: S,
+    ) -> impl Future<Output = ParseResult<()>> + Send
+    where
+        W: AsyncWrite + Unpin + Send,
+        S: Stream<Item = ParseResult<Transaction>> + Unpin + Send;
+}
+
+/// Следующий элемент потока
+async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+impl AsyncFormat for BinaryFormat {
+    type AsyncRecords<R: AsyncRead + Unpin> = AsyncBinaryRecords<R>;
+
+    fn records_async<R: AsyncRead + Unpin>(&self, reader: R) -> Self::AsyncRecords<R> {
+        AsyncPushRecords::new(reader, self.push_parser())
+    }
+
+    async fn write_records_async<W, S>(&self, mut writer: W, mut records: S) -> ParseResult<()>
+    where
+        W: AsyncWrite + Unpin + Send,
+        S: Stream<Item = ParseResult<Transaction>> + Unpin + Send,
+    {
+        let mut buffer = Vec::new();
+        self.write_header(&mut buffer, None)?;
+        let mut totals = Totals::default();
+        while let Some(transaction) = next(&mut records).await {
+            let transaction = transaction?;
+            self.write_transaction(&mut buffer, &transaction)?;
+            totals.add(&transaction);
+            if buffer.len() >= WRITE_BUFFER {
+                writer.write_all(&buffer).await?;
+                buffer.clear();
+            }
+        }
+        if self.trailer {
+            binary::write_trailer(&mut buffer, totals)?;
+        }
+        writer.write_all(&buffer).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+impl AsyncFormat for CsvFormat {
+    type AsyncRecords<R: AsyncRead + Unpin> = AsyncCsvRecords<R>;
+
+    fn records_async<R: AsyncRead + Unpin>(&self, reader: R) -> Self::AsyncRecords<R> {
+        AsyncPushRecords::new(reader, self.push_parser())
+    }
+
+    /// Набор необязательных колонок определяется по всем транзакциям,
+    /// поэтому транзакции собираются в память, как и в [`Format::write_records`].
+    async fn write_records_async<W, S>(&self, mut writer: W, mut records: S) -> ParseResult<()>
+    where
+        W: AsyncWrite + Unpin + Send,
+        S: Stream<Item = ParseResult<Transaction>> + Unpin + Send,
+    {
+        let mut transactions = Vec::new();
+        while let Some(transaction) = next(&mut records).await {
+            transactions.push(transaction?);
+        }
+        let mut buffer = Vec::new();
+        self.write_to(&mut buffer, &transactions)?;
+        writer.write_all(&buffer).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+impl AsyncFormat for TextFormat {
+    type AsyncRecords<R: AsyncRead + Unpin> = AsyncTextRecords<R>;
+
+    fn records_async<R: AsyncRead + Unpin>(&self, reader: R) -> Self::AsyncRecords<R> {
+        AsyncPushRecords::new(reader, self.push_parser())
+    }
+
+    async fn write_records_async<W, S>(&self, mut writer: W, mut records: S) -> ParseResult<()>
+    where
+        W: AsyncWrite + Unpin + Send,
+        S: Stream<Item = ParseResult<Transaction>> + Unpin + Send,
+    {
+        let mut buffer = Vec::new();
+        let mut totals = Totals::default();
+        while let Some(transaction) = next(&mut records).await {
+            let transaction = transaction?;
+            TextFormat::check_transaction(&transaction)?;
+            if totals.count > 0 {
+                buffer.push(b'\n');
+            }
+            TextFormat::write_transaction(&mut buffer, &transaction)?;
+            totals.add(&transaction);
+            if buffer.len() >= WRITE_BUFFER {
+                writer.write_all(&buffer).await?;
+                buffer.clear();
+            }
+        }
+        if self.trailer {
+            text::write_trailer(&mut buffer, totals)?;
+        }
+        writer.write_all(&buffer).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Асинхронный потоковый читатель поверх [`PushParser`]
+///
+/// Для каждого формата есть псевдоним: [`AsyncBinaryRecords`],
+/// [`AsyncCsvRecords`], [`AsyncTextRecords`].
+pub struct AsyncPushRecords<R, P> {
+    reader: R,
+    parser: P,
+    buffer: Box<[u8]>,
+    queue: Queue,
+}
+
+impl<R, P> AsyncPushRecords<R, P> {
+    fn new(reader: R, parser: P) -> Self {
+        AsyncPushRecords {
+            reader,
+            parser,
+            buffer: vec![0; READ_CHUNK].into_boxed_slice(),
+            queue: Queue::new(),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, P: PushParser + Unpin> Stream for AsyncPushRecords<R, P> {
+    type Item = ParseResult<Transaction>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(result) = this.queue.pop() {
+                return Poll::Ready(Some(result));
+            }
+            if this.queue.is_end() {
+                return Poll::Ready(None);
+            }
+
+            let mut read = ReadBuf::new(&mut this.buffer);
+            match ready!(Pin::new(&mut this.reader).poll_read(cx, &mut read)) {
+                Ok(()) => this.queue.push(&mut this.parser, read.filled()),
+                Err(e) => this.queue.fail(e.into()),
+            }
+        }
+    }
+}
+
+/// Асинхронный потоковый читатель формата YPBankBin
+pub type AsyncBinaryRecords<R> = AsyncPushRecords<R, BinaryPushParser>;
+
+impl<R> AsyncBinaryRecords<R> {
+    /// Заголовок файла, если он есть и уже прочитан
+    ///
+    /// Заголовок читается вместе с первой записью.
+    pub fn header(&self) -> Option<&FileHeader> {
+        self.parser.header()
+    }
+}
+
+/// Асинхронный потоковый читатель формата YPBankCsv
+pub type AsyncCsvRecords<R> = AsyncPushRecords<R, CsvPushParser>;
+
+/// Асинхронный потоковый читатель формата YPBankText
+pub type AsyncTextRecords<R> = AsyncPushRecords<R, TextPushParser>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileHeader, ParseError, TrailerPolicy};
+
+    fn sample() -> ParseResult<Vec<Transaction>> {
+        (1..=300)
+            .map(|i| {
+                Transaction::transfer(i % 10 + 1, i % 7 + 1, i as i64)
+                    .tx_id(i)
+                    .timestamp(1000 + i)
+                    .description(format!("Перевод \"№{i}\""))
+                    .metadata("channel", "app")
+                    .build()
+            })
+            .collect()
+    }
+
+    /// Поток из вектора для записи
+    struct Records(std::vec::IntoIter<Transaction>);
+
+    impl Stream for Records {
+        type Item = ParseResult<Transaction>;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.0.next().map(Ok))
+        }
+    }
+
+    async fn roundtrip<F: AsyncFormat + Sync>(format: &F) -> ParseResult<()> {
+        let transactions = sample()?;
+        let mut buffer = Vec::new();
+        format
+            .write_records_async(&mut buffer, Records(transactions.clone().into_iter()))
+            .await?;
+
+        // Дуплекс с маленьким буфером дробит чтение на части
+        let (mut client, server) = tokio::io::duplex(61);
+        let writer = async {
+            client.write_all(&buffer).await?;
+            drop(client);
+            Ok::<_, ParseError>(())
+        };
+        let reader = async {
+            let mut records = format.records_async(server);
+            let mut read = Vec::new();
+            while let Some(transaction) = next(&mut records).await {
+                read.push(transaction?);
+            }
+            Ok::<_, ParseError>(read)
+        };
+        let ((), read) = tokio::try_join!(writer, reader)?;
+        assert_eq!(read, transactions);
+        assert_eq!(format.read_from(buffer.as_slice())?, transactions);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_async_roundtrip_all_formats() -> ParseResult<()> {
+        roundtrip(
+            &BinaryFormat::new()
+                .with_header(FileHeader::new("test"))
+                .with_trailer(true),
+        )
+        .await?;
+        roundtrip(&CsvFormat::new().with_trailer(true)).await?;
+        roundtrip(&TextFormat::new().with_trailer(true)).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_async_errors_match_sync() -> ParseResult<()> {
+        let transactions = sample()?;
+        let format = BinaryFormat::new().with_trailer_policy(TrailerPolicy::Require);
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &transactions)?;
+
+        let mut records = format.records_async(buffer.as_slice());
+        let mut last = None;
+        while let Some(result) = next(&mut records).await {
+            last = Some(result);
+        }
+        assert!(matches!(last, Some(Err(ParseError::MissingTrailer))));
+
+        let mut records = format.records_async(&buffer[..buffer.len() - 1]);
+        let mut last = None;
+        while let Some(result) = next(&mut records).await {
+            last = Some(result);
+        }
+        assert!(matches!(last, Some(Err(ParseError::Io(_)))));
+
+        let mut records = CsvFormat::new().records_async(&b"TX_ID,X\n"[..]);
+        let result = next(&mut records).await;
+        assert!(matches!(result, Some(Err(ParseError::InvalidFormat(_)))));
+        assert!(next(&mut records).await.is_none());
+        Ok(())
+    }
+}
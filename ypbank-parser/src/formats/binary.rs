@@ -1,45 +1,332 @@
 use super::Format;
-use crate::{ParseError, ParseResult, Transaction, TransactionStatus, TransactionType};
+use super::index::{BinaryIndex, IndexedReader};
+use super::mapped::{BorrowedRecords, MetadataRefs, TransactionRef};
+use super::parallel;
+use super::push::{PushParser, PushRecords};
+use super::trailer::{Totals, TrailerCheck, TrailerPolicy};
+use crate::{
+    ParseError, ParseResult, Transaction, TransactionStatus, TransactionType, UnknownCodePolicy,
+};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use std::io::{Read, Write};
-const MAGIC: [u8; 4] = [0x59, 0x50, 0x42, 0x4E];
+use std::io::{Read, Seek, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+pub(super) const MAGIC: [u8; 4] = [0x59, 0x50, 0x42, 0x4E];
+/// Заголовок файла: `YPBH`, u32 размер тела, затем байт версии, байт
+/// флагов, u64 время создания, строка производителя с префиксом длины u32
+/// и поля, отмеченные флагами, в порядке битов
+pub(super) const HEADER_MAGIC: [u8; 4] = [0x59, 0x50, 0x42, 0x48];
+const HEADER_VERSION: u8 = 1;
+/// u64 число записей в файле
+const HEADER_FLAG_RECORD_COUNT: u8 = 0b0000_0001;
+/// Файл заканчивается итоговой записью
+const HEADER_FLAG_TRAILER: u8 = 0b0000_0010;
+const HEADER_KNOWN_FLAGS: u8 = HEADER_FLAG_RECORD_COUNT | HEADER_FLAG_TRAILER;
+/// Итоговая запись: `YPBT`, u32 размер тела, u64 число записей и i128
+/// сумма AMOUNT
+pub(super) const TRAILER_MAGIC: [u8; 4] = [0x59, 0x50, 0x42, 0x54];
+pub(super) const TRAILER_SIZE: u32 = 8 + 16;
+/// Предел размера тела элемента по умолчанию
+pub(super) const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// Версия записи с блоком расширения после описания:
+/// байт версии, байт флагов и поля, отмеченные флагами, в порядке битов
+const RECORD_V2: u8 = 2;
+const FLAG_PARENT_TX_ID: u8 = 0b0000_0001;
+/// Метаданные: u32 число пар, затем для каждой пары u32 длина и байты
+/// ключа, u32 длина и байты значения
+const FLAG_METADATA: u8 = 0b0000_0010;
+const KNOWN_FLAGS: u8 = FLAG_PARENT_TX_ID | FLAG_METADATA;
+
+/// Заголовок файла YPBankBin
+///
+/// Заголовок необязателен: файлы без него читаются как прежде. При записи
+/// `record_count` и `trailer` заполняет [`BinaryFormat`]: число записей
+/// известно только для [`Format::write_to`], при потоковой записи его нет.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    /// Время создания файла, миллисекунды Unix
+    pub created: u64,
+    /// Система, создавшая файл
+    pub producer: String,
+    /// Число записей в файле
+    pub record_count: Option<u64>,
+    /// Файл заканчивается итоговой записью
+    pub trailer: bool,
+}
+
+impl FileHeader {
+    /// Заголовок с текущим временем создания
+    pub fn new(producer: impl Into<String>) -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        FileHeader {
+            created,
+            producer: producer.into(),
+            record_count: None,
+            trailer: false,
+        }
+    }
+}
 
 ///Парсер для бинарного формата YPBankBin
-pub struct BinaryFormat;
+#[derive(Debug, Clone)]
+pub struct BinaryFormat {
+    unknown_codes: UnknownCodePolicy,
+    pub(super) trailer: bool,
+    trailer_policy: TrailerPolicy,
+    header: Option<FileHeader>,
+    max_frame_size: usize,
+}
+
+impl Default for BinaryFormat {
+    fn default() -> Self {
+        BinaryFormat {
+            unknown_codes: UnknownCodePolicy::default(),
+            trailer: false,
+            trailer_policy: TrailerPolicy::default(),
+            header: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
 
 impl BinaryFormat {
-    /// Читает одну транзакцию из бинарного формата
-    fn read_transaction<R: Read>(reader: &mut R) -> ParseResult<Option<Transaction>> {
-        let mut magic = [0u8; 4];
+    /// Создаёт парсер с настройками по умолчанию
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Задаёт политику для неизвестных кодов типа и статуса
+    pub fn with_unknown_codes(mut self, policy: UnknownCodePolicy) -> Self {
+        self.unknown_codes = policy;
+        self
+    }
+
+    /// Дописывать ли в конец итоговую запись с числом записей и суммой
+    pub fn with_trailer(mut self, trailer: bool) -> Self {
+        self.trailer = trailer;
+        self
+    }
+
+    /// Задаёт, обязательна ли итоговая запись при чтении
+    pub fn with_trailer_policy(mut self, policy: TrailerPolicy) -> Self {
+        self.trailer_policy = policy;
+        self
+    }
+
+    /// Записывать в начало файла заголовок
+    pub fn with_header(mut self, header: FileHeader) -> Self {
+        self.header = Some(header);
+        self
+    }
+
+    /// Задаёт предел размера тела элемента при потоковом чтении, по умолчанию 1 МиБ
+    ///
+    /// Испорченный размер в префиксе иначе заставил бы копить в памяти до
+    /// 4 ГиБ в ожидании конца элемента.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Чтение с произвольным доступом по индексу файла
+    ///
+    /// Если размер или отпечаток начала и конца файла не совпадают с теми,
+    /// по которым строился индекс, возвращается `ParseError::StaleIndex`.
+    pub fn indexed<R: Read + Seek>(
+        &self,
+        reader: R,
+        index: BinaryIndex,
+    ) -> ParseResult<IndexedReader<R>> {
+        IndexedReader::new(self.clone(), reader, index)
+    }
+
+    /// Читает записи из буфера в памяти, не копируя строки
+    ///
+    /// Для файлов на диске удобнее [`super::MappedFile::records`].
+    pub fn borrowed_records<'a>(&self, data: &'a [u8]) -> BorrowedRecords<'a> {
+        BorrowedRecords::new(FrameDecoder::new(self.clone()), data)
+    }
+
+    /// Читает буфер с данными в `threads` потоков
+    ///
+    /// Быстрый проход по размерам записей делит буфер на куски по границам
+    /// записей, куски разбираются параллельно, а транзакции возвращаются в
+    /// порядке файла. Заголовок, итог и число записей проверяются так же,
+    /// как при потоковом чтении.
+    pub fn read_parallel(&self, data: &[u8], threads: usize) -> ParseResult<Vec<Transaction>> {
+        let mut trailer = TrailerCheck::new(self.trailer_policy);
+        let mut header = None;
+        let mut rest = data;
+        if data.starts_with(&HEADER_MAGIC) {
+            let (_, body, tail) = split_entry(data)?;
+            let decoded = decode_header(body)?;
+            if decoded.trailer {
+                trailer.require();
+            }
+            header = Some(decoded);
+            rest = tail;
+        }
+
+        let target = rest
+            .len()
+            .div_ceil(parallel::chunk_count(rest.len(), threads));
+        let mut chunks = Vec::new();
+        let mut chunk_start = rest;
+        let mut chunk_len = 0;
+        let mut truncated = None;
+        while chunk_len < chunk_start.len() {
+            match split_entry(&chunk_start[chunk_len..]) {
+                Ok((_, body, _)) => chunk_len += 4 + 4 + body.len(),
+                Err(e) => {
+                    truncated = Some(e);
+                    break;
+                }
+            }
+            if chunk_len >= target {
+                let (chunk, tail) = chunk_start.split_at(chunk_len);
+                chunks.push(chunk);
+                chunk_start = tail;
+                chunk_len = 0;
+            }
+        }
+        if chunk_len > 0 {
+            chunks.push(&chunk_start[..chunk_len]);
+        }
 
-        match reader.read_exact(&mut magic) {
-            Ok(()) => (),
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
-            Err(e) => return Err(e.into()),
+        let transactions = parallel::decode_chunks(&chunks, &mut trailer, |mut chunk, entries| {
+            while !chunk.is_empty() {
+                let (magic, body, tail) = split_entry(chunk)?;
+                chunk = tail;
+                entries.push(match magic {
+                    MAGIC => Entry::Transaction(self.decode_record(body)?),
+                    TRAILER_MAGIC => Entry::Trailer(decode_trailer(body)?),
+                    HEADER_MAGIC => {
+                        return Err(ParseError::InvalidFormat(
+                            "Заголовок файла не в начале".to_string(),
+                        ));
+                    }
+                    _ => return Err(ParseError::InvalidMagic),
+                });
+            }
+            Ok(())
+        })?;
+        if let Some(e) = truncated {
+            return Err(e);
         }
 
+        trailer.finish()?;
+        match header.and_then(|header| header.record_count) {
+            Some(expected) if expected != transactions.len() as u64 => {
+                Err(ParseError::RecordCountMismatch {
+                    expected,
+                    actual: transactions.len() as u64,
+                })
+            }
+            _ => Ok(transactions),
+        }
+    }
+
+    /// Записывает заголовок, если он задан
+    pub(super) fn write_header<W: Write>(
+        &self,
+        writer: &mut W,
+        record_count: Option<u64>,
+    ) -> ParseResult<()> {
+        let Some(header) = &self.header else {
+            return Ok(());
+        };
+
+        let mut flags = 0;
+        if record_count.is_some() {
+            flags |= HEADER_FLAG_RECORD_COUNT;
+        }
+        if self.trailer {
+            flags |= HEADER_FLAG_TRAILER;
+        }
+
+        let mut body = Vec::new();
+        body.write_u8(HEADER_VERSION)?;
+        body.write_u8(flags)?;
+        body.write_u64::<BigEndian>(header.created)?;
+        write_string(&mut body, &header.producer)?;
+        if let Some(count) = record_count {
+            body.write_u64::<BigEndian>(count)?;
+        }
+
+        writer.write_all(&HEADER_MAGIC)?;
+        writer.write_u32::<BigEndian>(body.len() as u32)?;
+        writer.write_all(&body)?;
+        Ok(())
+    }
+
+    /// Читает одну транзакцию или итоговую запись после магического числа
+    pub(super) fn read_entry<R: Read>(&self, reader: &mut R, magic: [u8; 4]) -> ParseResult<Entry> {
+        if magic == HEADER_MAGIC {
+            return Err(ParseError::InvalidFormat(
+                "Заголовок файла не в начале".to_string(),
+            ));
+        }
+        if magic == TRAILER_MAGIC {
+            if reader.read_u32::<BigEndian>()? != TRAILER_SIZE {
+                return Err(ParseError::RecordSizeMismatch);
+            }
+            let mut body = [0u8; TRAILER_SIZE as usize];
+            reader.read_exact(&mut body)?;
+            return decode_trailer(&body).map(Entry::Trailer);
+        }
         if magic != MAGIC {
             return Err(ParseError::InvalidMagic);
         }
 
-        let _record_size = reader.read_u32::<BigEndian>()?;
+        let body = read_body(reader)?;
+        self.decode_record(&body).map(Entry::Transaction)
+    }
 
-        let tx_id = reader.read_u64::<BigEndian>()?;
-        let tx_type_byte = reader.read_u8()?;
-        let tx_type = TransactionType::try_from(tx_type_byte)?;
-        let from_user_id = reader.read_u64::<BigEndian>()?;
-        let to_user_id = reader.read_u64::<BigEndian>()?;
-        let amount = reader.read_i64::<BigEndian>()?;
-        let timestamp = reader.read_u64::<BigEndian>()?;
-        let status_byte = reader.read_u8()?;
-        let status = TransactionStatus::try_from(status_byte)?;
-        let description_len = reader.read_u32::<BigEndian>()?;
+    /// Разбирает тело записи длиной `record_size`
+    fn decode_record(&self, body: &[u8]) -> ParseResult<Transaction> {
+        self.decode_ref(body)?.to_transaction()
+    }
+
+    /// Разбирает тело записи, оставляя описание и метаданные в буфере
+    pub(super) fn decode_ref<'a>(&self, mut body: &'a [u8]) -> ParseResult<TransactionRef<'a>> {
+        let tx_id = body.read_u64::<BigEndian>()?;
+        let tx_type_byte = body.read_u8()?;
+        let tx_type = TransactionType::from_code(tx_type_byte, self.unknown_codes)?;
+        let from_user_id = body.read_u64::<BigEndian>()?;
+        let to_user_id = body.read_u64::<BigEndian>()?;
+        let amount = body.read_i64::<BigEndian>()?;
+        let timestamp = body.read_u64::<BigEndian>()?;
+        let status_byte = body.read_u8()?;
+        let status = TransactionStatus::from_code(status_byte, self.unknown_codes)?;
+        let description = split_prefixed(&mut body)?;
+        let mut extension = body;
 
-        let mut description_bytes = vec![0u8; description_len as usize];
-        reader.read_exact(&mut description_bytes)?;
-        let description = String::from_utf8(description_bytes)?;
+        let mut parent_tx_id = None;
+        let mut metadata = MetadataRefs::default();
+        if !extension.is_empty() {
+            let version = extension.read_u8()?;
+            if version != RECORD_V2 {
+                return Err(ParseError::InvalidFormat(format!(
+                    "Неподдерживаемая версия записи: {version}"
+                )));
+            }
+            let flags = extension.read_u8()?;
+            // Поля с неизвестными флагами идут после известных, их можно пропустить
+            let strict = flags & !KNOWN_FLAGS == 0;
+            if flags & FLAG_PARENT_TX_ID != 0 {
+                parent_tx_id = Some(extension.read_u64::<BigEndian>()?);
+            }
+            if flags & FLAG_METADATA != 0 {
+                let count = extension.read_u32::<BigEndian>()?;
+                metadata = MetadataRefs::new(extension, count, strict);
+            } else if strict && !extension.is_empty() {
+                return Err(ParseError::RecordSizeMismatch);
+            }
+        }
 
-        Ok(Some(Transaction {
+        Ok(TransactionRef {
             tx_id,
             tx_type,
             from_user_id,
@@ -47,54 +334,401 @@ impl BinaryFormat {
             amount,
             timestamp,
             status,
+            parent_tx_id,
             description,
-        }))
+            metadata,
+        })
     }
 
     /// Записывает одну транзакцию в бинарном формате
-    fn write_transaction<W: Write>(
+    ///
+    /// Запись без необязательных полей сохраняется в версии 1, чтобы её
+    /// могли прочитать прежние версии библиотеки.
+    pub(super) fn write_transaction<W: Write>(
         &self,
         writer: &mut W,
         transaction: &Transaction,
     ) -> ParseResult<()> {
-        writer.write_all(&MAGIC)?;
-
         let description_bytes = transaction.description.as_bytes();
-        let record_size = 8 + 1 + 8 + 8 + 8 + 8 + 1 + 4 + description_bytes.len();
+        let mut body = Vec::with_capacity(8 + 1 + 8 + 8 + 8 + 8 + 1 + 4 + description_bytes.len());
 
-        writer.write_u32::<BigEndian>(record_size as u32)?;
+        body.write_u64::<BigEndian>(transaction.tx_id)?;
+        body.write_u8(transaction.tx_type.into())?;
+        body.write_u64::<BigEndian>(transaction.from_user_id)?;
+        body.write_u64::<BigEndian>(transaction.to_user_id)?;
+        body.write_i64::<BigEndian>(transaction.amount)?;
+        body.write_u64::<BigEndian>(transaction.timestamp)?;
+        body.write_u8(transaction.status.into())?;
+        body.write_u32::<BigEndian>(description_bytes.len() as u32)?;
+        body.write_all(description_bytes)?;
 
-        writer.write_u64::<BigEndian>(transaction.tx_id)?;
-        writer.write_u8(transaction.tx_type.into())?;
-        writer.write_u64::<BigEndian>(transaction.from_user_id)?;
-        writer.write_u64::<BigEndian>(transaction.to_user_id)?;
-        writer.write_i64::<BigEndian>(transaction.amount)?;
-        writer.write_u64::<BigEndian>(transaction.timestamp)?;
-        writer.write_u8(transaction.status.into())?;
-        writer.write_u32::<BigEndian>(description_bytes.len() as u32)?;
-        writer.write_all(description_bytes)?;
+        let mut flags = 0;
+        if transaction.parent_tx_id.is_some() {
+            flags |= FLAG_PARENT_TX_ID;
+        }
+        if !transaction.metadata.is_empty() {
+            flags |= FLAG_METADATA;
+        }
+
+        if flags != 0 {
+            body.write_u8(RECORD_V2)?;
+            body.write_u8(flags)?;
+        }
+        if let Some(parent_tx_id) = transaction.parent_tx_id {
+            body.write_u64::<BigEndian>(parent_tx_id)?;
+        }
+        if !transaction.metadata.is_empty() {
+            body.write_u32::<BigEndian>(transaction.metadata.len() as u32)?;
+            for (key, value) in &transaction.metadata {
+                write_string(&mut body, key)?;
+                write_string(&mut body, value)?;
+            }
+        }
+
+        writer.write_all(&MAGIC)?;
+        writer.write_u32::<BigEndian>(body.len() as u32)?;
+        writer.write_all(&body)?;
 
         Ok(())
     }
 }
 
-impl Format for BinaryFormat {
-    fn read_from<R: Read>(&self, mut reader: R) -> ParseResult<Vec<Transaction>> {
-        let mut transactions = Vec::new();
-        loop {
-            match Self::read_transaction(&mut reader) {
-                Ok(Some(transaction)) => transactions.push(transaction),
-                Ok(None) => break,
-                Err(e) => return Err(e),
+/// Читает магическое число; `None` — источник закончился
+pub(super) fn read_magic<R: Read>(reader: &mut R) -> ParseResult<Option<[u8; 4]>> {
+    let mut magic = [0u8; 4];
+    match reader.read_exact(&mut magic) {
+        Ok(()) => Ok(Some(magic)),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Отделяет от буфера магическое число, тело очередного элемента и остаток
+pub(super) fn split_entry(mut data: &[u8]) -> ParseResult<([u8; 4], &[u8], &[u8])> {
+    let mut magic = [0u8; 4];
+    data.read_exact(&mut magic)?;
+    let size = data.read_u32::<BigEndian>()? as usize;
+    if size > data.len() {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    let (body, rest) = data.split_at(size);
+    Ok((magic, body, rest))
+}
+
+/// Читает тело с префиксом размера u32
+fn read_body<R: Read>(reader: &mut R) -> ParseResult<Vec<u8>> {
+    let size = reader.read_u32::<BigEndian>()?;
+
+    let mut body = Vec::new();
+    reader.take(size as u64).read_to_end(&mut body)?;
+    if body.len() != size as usize {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(body)
+}
+
+/// Разбирает тело заголовка файла
+pub(super) fn decode_header(mut body: &[u8]) -> ParseResult<FileHeader> {
+    let version = body.read_u8()?;
+    if version != HEADER_VERSION {
+        return Err(ParseError::InvalidFormat(format!(
+            "Неподдерживаемая версия заголовка: {version}"
+        )));
+    }
+    let flags = body.read_u8()?;
+    let created = body.read_u64::<BigEndian>()?;
+    let producer = read_string(&mut body)?;
+    let record_count = if flags & HEADER_FLAG_RECORD_COUNT != 0 {
+        Some(body.read_u64::<BigEndian>()?)
+    } else {
+        None
+    };
+    // Поля с неизвестными флагами идут после известных, их можно пропустить
+    if flags & !HEADER_KNOWN_FLAGS == 0 && !body.is_empty() {
+        return Err(ParseError::RecordSizeMismatch);
+    }
+
+    Ok(FileHeader {
+        created,
+        producer,
+        record_count,
+        trailer: flags & HEADER_FLAG_TRAILER != 0,
+    })
+}
+
+/// Разбирает тело итоговой записи
+pub(super) fn decode_trailer(mut body: &[u8]) -> ParseResult<Totals> {
+    if body.len() != TRAILER_SIZE as usize {
+        return Err(ParseError::RecordSizeMismatch);
+    }
+    let count = body.read_u64::<BigEndian>()?;
+    let amount = body.read_i128::<BigEndian>()?;
+    Ok(Totals { count, amount })
+}
+
+/// Записывает итоговую запись
+pub(super) fn write_trailer<W: Write>(writer: &mut W, totals: Totals) -> ParseResult<()> {
+    writer.write_all(&TRAILER_MAGIC)?;
+    writer.write_u32::<BigEndian>(TRAILER_SIZE)?;
+    writer.write_u64::<BigEndian>(totals.count)?;
+    writer.write_i128::<BigEndian>(totals.amount)?;
+    Ok(())
+}
+
+/// Элемент бинарного потока
+pub(super) enum Entry {
+    Transaction(Transaction),
+    Trailer(Totals),
+}
+
+/// Читает строку с префиксом длины u32 из тела записи
+fn read_string(body: &mut &[u8]) -> ParseResult<String> {
+    Ok(String::from_utf8(split_prefixed(body)?.to_vec())?)
+}
+
+/// Отделяет от тела записи байты с префиксом длины u32
+pub(super) fn split_prefixed<'a>(body: &mut &'a [u8]) -> ParseResult<&'a [u8]> {
+    let len = body.read_u32::<BigEndian>()? as usize;
+    if len > body.len() {
+        return Err(ParseError::RecordSizeMismatch);
+    }
+    let (bytes, rest) = body.split_at(len);
+    *body = rest;
+    Ok(bytes)
+}
+
+/// Записывает строку с префиксом длины u32
+fn write_string(body: &mut Vec<u8>, value: &str) -> ParseResult<()> {
+    body.write_u32::<BigEndian>(value.len() as u32)?;
+    body.write_all(value.as_bytes())?;
+    Ok(())
+}
+
+/// Разбор элементов YPBankBin по одному
+///
+/// Общая часть всех читателей: получает магическое число и тело каждого
+/// элемента, разбирает заголовок, сверяет итог и число записей.
+pub(super) struct FrameDecoder {
+    format: BinaryFormat,
+    header: Option<FileHeader>,
+    /// Элементы уже были: заголовок может быть только первым
+    started: bool,
+    records: u64,
+    trailer: TrailerCheck,
+}
+
+impl FrameDecoder {
+    pub(super) fn new(format: BinaryFormat) -> Self {
+        let trailer = TrailerCheck::new(format.trailer_policy);
+        FrameDecoder {
+            format,
+            header: None,
+            started: false,
+            records: 0,
+            trailer,
+        }
+    }
+
+    /// Заголовок файла, если он уже прочитан
+    pub(super) fn header(&self) -> Option<&FileHeader> {
+        self.header.as_ref()
+    }
+
+    /// Разбирает элемент; транзакция возвращается без копирования строк
+    pub(super) fn decode<'a>(
+        &mut self,
+        magic: [u8; 4],
+        body: &'a [u8],
+    ) -> ParseResult<Option<TransactionRef<'a>>> {
+        let first = !self.started;
+        self.started = true;
+
+        match magic {
+            MAGIC => {
+                let transaction = self.format.decode_ref(body)?;
+                self.trailer.record(transaction.tx_id, transaction.amount)?;
+                self.records += 1;
+                Ok(Some(transaction))
+            }
+            TRAILER_MAGIC => {
+                self.trailer.trailer(decode_trailer(body)?)?;
+                Ok(None)
+            }
+            HEADER_MAGIC if first => {
+                let header = decode_header(body)?;
+                if header.trailer {
+                    self.trailer.require();
+                }
+                self.header = Some(header);
+                Ok(None)
             }
+            HEADER_MAGIC => Err(ParseError::InvalidFormat(
+                "Заголовок файла не в начале".to_string(),
+            )),
+            _ => Err(ParseError::InvalidMagic),
         }
+    }
+
+    /// Проверяет размер тела элемента до того, как его начнут копить
+    fn check_size(&self, size: usize) -> ParseResult<()> {
+        if size > self.format.max_frame_size {
+            return Err(ParseError::FrameTooLarge {
+                size,
+                max: self.format.max_frame_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Проверяет итог и число записей из заголовка в конце источника
+    pub(super) fn finish(&self) -> ParseResult<()> {
+        self.trailer.finish()?;
+        match self.header.as_ref().and_then(|header| header.record_count) {
+            Some(expected) if expected != self.records => Err(ParseError::RecordCountMismatch {
+                expected,
+                actual: self.records,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Инкрементальный разбор YPBankBin
+///
+/// Копит байты незавершённого элемента между порциями; целые элементы
+/// разбираются прямо из переданной порции. Создаётся
+/// [`Format::push_parser`].
+pub struct BinaryPushParser {
+    decoder: FrameDecoder,
+    /// Начало незавершённого элемента
+    buffer: Vec<u8>,
+}
+
+impl BinaryPushParser {
+    /// Заголовок файла, если он есть и уже разобран
+    ///
+    /// Заголовок разбирается вместе с первым элементом.
+    pub fn header(&self) -> Option<&FileHeader> {
+        self.decoder.header()
+    }
+
+    /// Первый элемент уже разобран, и наличие заголовка известно
+    pub(super) fn started(&self) -> bool {
+        self.decoder.started
+    }
+}
+
+/// Разбирает целые элементы из начала `data`; возвращает число разобранных байт
+fn decode_frames(
+    decoder: &mut FrameDecoder,
+    data: &[u8],
+    out: &mut Vec<Transaction>,
+) -> ParseResult<usize> {
+    let mut rest = data;
+    while let [m0, m1, m2, m3, ..] = *rest {
+        let magic = [m0, m1, m2, m3];
+        if !matches!(magic, MAGIC | TRAILER_MAGIC | HEADER_MAGIC) {
+            return Err(ParseError::InvalidMagic);
+        }
+        let [_, _, _, _, s0, s1, s2, s3, ..] = *rest else {
+            break;
+        };
+        let size = u32::from_be_bytes([s0, s1, s2, s3]) as usize;
+        decoder.check_size(size)?;
+        let end = 8 + size;
+        let Some(body) = rest.get(8..end) else {
+            break;
+        };
+        if let Some(transaction) = decoder.decode(magic, body)? {
+            out.push(transaction.to_transaction()?);
+        }
+        rest = &rest[end..];
+    }
+    Ok(data.len() - rest.len())
+}
+
+impl PushParser for BinaryPushParser {
+    fn feed_into(&mut self, data: &[u8], out: &mut Vec<Transaction>) -> ParseResult<()> {
+        if self.buffer.is_empty() {
+            let used = decode_frames(&mut self.decoder, data, out)?;
+            self.buffer.extend_from_slice(&data[used..]);
+        } else {
+            self.buffer.extend_from_slice(data);
+            let used = decode_frames(&mut self.decoder, &self.buffer, out)?;
+            self.buffer.drain(..used);
+        }
+        Ok(())
+    }
+
+    fn finish_into(&mut self, _out: &mut Vec<Transaction>) -> ParseResult<()> {
+        if !self.buffer.is_empty() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        self.decoder.finish()
+    }
+}
+
+/// Потоковый читатель формата YPBankBin
+pub type BinaryRecords<R> = PushRecords<R, BinaryPushParser>;
 
-        Ok(transactions)
+impl<R: Read> BinaryRecords<R> {
+    /// Заголовок файла, если он есть
+    ///
+    /// Заголовок читается при первом обращении к источнику, поэтому метод
+    /// можно вызвать до чтения записей.
+    pub fn header(&mut self) -> ParseResult<Option<&FileHeader>> {
+        while !self.parser.started() && self.fill() {}
+        if !self.parser.started()
+            && let Some(e) = self.take_error()
+        {
+            return Err(e);
+        }
+        Ok(self.parser.header())
+    }
+}
+
+impl Format for BinaryFormat {
+    type Records<R: Read> = BinaryRecords<R>;
+    type Parser = BinaryPushParser;
+
+    fn records<R: Read>(&self, reader: R) -> Self::Records<R> {
+        PushRecords::new(reader, self.push_parser())
+    }
+
+    fn push_parser(&self) -> Self::Parser {
+        BinaryPushParser {
+            decoder: FrameDecoder::new(self.clone()),
+            buffer: Vec::new(),
+        }
     }
 
     fn write_to<W: Write>(&self, mut writer: W, transactions: &[Transaction]) -> ParseResult<()> {
+        self.write_header(&mut writer, Some(transactions.len() as u64))?;
+        let mut totals = Totals::default();
         for transaction in transactions {
             self.write_transaction(&mut writer, transaction)?;
+            totals.add(transaction);
+        }
+        if self.trailer {
+            write_trailer(&mut writer, totals)?;
+        }
+        Ok(())
+    }
+
+    fn write_records<W, I>(&self, mut writer: W, records: I) -> ParseResult<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = ParseResult<Transaction>>,
+    {
+        self.write_header(&mut writer, None)?;
+        let mut totals = Totals::default();
+        for transaction in records {
+            let transaction = transaction?;
+            self.write_transaction(&mut writer, &transaction)?;
+            totals.add(&transaction);
+        }
+        if self.trailer {
+            write_trailer(&mut writer, totals)?;
         }
         Ok(())
     }
@@ -110,21 +744,16 @@ mod tests {
 
     #[test]
     fn test_binary_roundtrip() -> ParseResult<()> {
-        let transaction = Transaction {
-            tx_id: 123456,
-            tx_type: TransactionType::Deposit,
-            from_user_id: 0,
-            to_user_id: 789,
-            amount: 10_000,
-            timestamp: 1633036800000,
-            status: TransactionStatus::Success,
-            description: "Test transaction".to_string(),
-        };
+        let transaction = Transaction::deposit(789, 10_000)
+            .tx_id(123456)
+            .timestamp(1633036800000)
+            .description("Test transaction")
+            .build()?;
 
-        let format = BinaryFormat;
+        let format = BinaryFormat::new();
         let mut buffer = Vec::new();
 
-        format.write_to(&mut buffer, &[transaction.clone()])?;
+        format.write_to(&mut buffer, std::slice::from_ref(&transaction))?;
 
         let result = format.read_from(Cursor::new(buffer))?;
 
@@ -137,7 +766,7 @@ mod tests {
     #[test]
     fn test_binary_invalid_magic() {
         let invalid_data = vec![0x00, 0x00, 0x00, 0x00];
-        let format = BinaryFormat;
+        let format = BinaryFormat::new();
         let result = format.read_from(Cursor::new(invalid_data));
         assert!(matches!(result, Err(ParseError::InvalidMagic)));
     }
@@ -145,17 +774,13 @@ mod tests {
     #[test]
     fn test_binary_truncated_record() {
         let mut buffer = Vec::new();
-        let format = BinaryFormat;
-        let tx = Transaction {
-            tx_id: 1,
-            tx_type: TransactionType::Deposit,
-            from_user_id: 0,
-            to_user_id: 2,
-            amount: 100,
-            timestamp: 1000,
-            status: TransactionStatus::Success,
-            description: "test".to_string(),
-        };
+        let format = BinaryFormat::new();
+        let tx = Transaction::deposit(2, 100)
+            .tx_id(1)
+            .timestamp(1000)
+            .description("test")
+            .build()
+            .unwrap();
         format.write_to(&mut buffer, &[tx]).unwrap();
         buffer.truncate(buffer.len() - 5);
         let result = format.read_from(Cursor::new(buffer));
@@ -165,31 +790,232 @@ mod tests {
     #[test]
     fn test_binary_multiple_transactions() {
         let txs = vec![
-            Transaction {
-                tx_id: 1,
-                tx_type: TransactionType::Deposit,
-                from_user_id: 0,
-                to_user_id: 2,
-                amount: 100,
-                timestamp: 1000,
-                status: TransactionStatus::Success,
-                description: "first".to_string(),
-            },
-            Transaction {
-                tx_id: 2,
-                tx_type: TransactionType::Transfer,
-                from_user_id: 2,
-                to_user_id: 3,
-                amount: 50,
-                timestamp: 2000,
-                status: TransactionStatus::Pending,
-                description: "".to_string(),
-            },
+            Transaction::deposit(2, 100)
+                .tx_id(1)
+                .timestamp(1000)
+                .description("first")
+                .build()
+                .unwrap(),
+            Transaction::transfer(2, 3, 50)
+                .tx_id(2)
+                .timestamp(2000)
+                .status(TransactionStatus::Pending)
+                .build()
+                .unwrap(),
         ];
-        let format = BinaryFormat;
+        let format = BinaryFormat::new();
         let mut buffer = Vec::new();
         format.write_to(&mut buffer, &txs).unwrap();
         let read_txs = format.read_from(Cursor::new(buffer)).unwrap();
         assert_eq!(read_txs, txs);
     }
+
+    #[test]
+    fn test_binary_unknown_codes() {
+        let mut tx = Transaction::transfer(2, 3, 50).tx_id(7).build().unwrap();
+        tx.tx_type = TransactionType::Unknown(42);
+        tx.status = TransactionStatus::Unknown(17);
+
+        let mut buffer = Vec::new();
+        BinaryFormat::new()
+            .write_to(&mut buffer, &[tx.clone()])
+            .unwrap();
+
+        let strict = BinaryFormat::new().read_from(Cursor::new(&buffer));
+        assert!(matches!(strict, Err(ParseError::InvalidTransactionType(_))));
+
+        let preserved = BinaryFormat::new()
+            .with_unknown_codes(UnknownCodePolicy::Preserve)
+            .read_from(Cursor::new(&buffer))
+            .unwrap();
+        assert_eq!(preserved, vec![tx]);
+    }
+
+    #[test]
+    fn test_binary_parent_tx_id() -> ParseResult<()> {
+        let original = Transaction::transfer(2, 3, 50).tx_id(1).build()?;
+        let refund = Transaction::builder()
+            .tx_id(2)
+            .tx_type(TransactionType::Refund)
+            .from_user_id(3)
+            .to_user_id(2)
+            .amount(50)
+            .parent_tx_id(1)
+            .build()?;
+
+        let format = BinaryFormat::new();
+        let mut v1 = Vec::new();
+        format.write_to(&mut v1, std::slice::from_ref(&original))?;
+        let mut v2 = Vec::new();
+        format.write_to(&mut v2, std::slice::from_ref(&refund))?;
+
+        // Расширение версии 2: байт версии, байт флагов и u64
+        assert_eq!(v2.len(), v1.len() + 1 + 1 + 8);
+        assert_eq!(format.read_from(Cursor::new(v2))?, vec![refund]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_record_size_mismatch() {
+        let tx = Transaction::deposit(2, 100).tx_id(1).build().unwrap();
+        let mut buffer = Vec::new();
+        BinaryFormat::new().write_to(&mut buffer, &[tx]).unwrap();
+
+        // Лишний байт после блока расширения без флагов
+        buffer[7] += 3;
+        buffer.extend_from_slice(&[RECORD_V2, 0, 0]);
+        let result = BinaryFormat::new().read_from(Cursor::new(buffer));
+        assert!(matches!(result, Err(ParseError::RecordSizeMismatch)));
+    }
+
+    #[test]
+    fn test_binary_metadata() -> ParseResult<()> {
+        let tx = Transaction::deposit(2, 100)
+            .tx_id(1)
+            .metadata("channel", "pos")
+            .metadata("mcc", "5411")
+            .build()?;
+
+        let format = BinaryFormat::new();
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, std::slice::from_ref(&tx))?;
+
+        let record_size = u32::from_be_bytes(buffer[4..8].try_into().unwrap()) as usize;
+        assert_eq!(record_size, buffer.len() - 8);
+        assert_eq!(format.read_from(Cursor::new(buffer))?, vec![tx]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_trailer() -> ParseResult<()> {
+        let txs = vec![
+            Transaction::deposit(2, 100).tx_id(1).build()?,
+            Transaction::withdrawal(2, 30).tx_id(2).build()?,
+        ];
+        let mut buffer = Vec::new();
+        BinaryFormat::new()
+            .with_trailer(true)
+            .write_to(&mut buffer, &txs)?;
+
+        let required = BinaryFormat::new().with_trailer_policy(TrailerPolicy::Require);
+        assert_eq!(required.read_from(Cursor::new(&buffer))?, txs);
+
+        // Файл без итога и без второй записи читается без обязательного итога
+        let cut = buffer.len() - (4 + 4 + TRAILER_SIZE as usize);
+        let first_len = cut / 2;
+        assert_eq!(
+            BinaryFormat::new()
+                .read_from(Cursor::new(&buffer[..first_len]))?
+                .len(),
+            1
+        );
+        assert!(matches!(
+            required.read_from(Cursor::new(&buffer[..first_len])),
+            Err(ParseError::MissingTrailer)
+        ));
+
+        let mut missing_record = buffer[..first_len].to_vec();
+        missing_record.extend_from_slice(&buffer[cut..]);
+        assert!(matches!(
+            BinaryFormat::new().read_from(Cursor::new(missing_record)),
+            Err(ParseError::TrailerMismatch { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_file_header() -> ParseResult<()> {
+        let txs = vec![
+            Transaction::deposit(2, 100).tx_id(1).build()?,
+            Transaction::deposit(2, 200).tx_id(2).build()?,
+        ];
+        let header = FileHeader::new("core-banking 4.2");
+        let format = BinaryFormat::new().with_header(header.clone());
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &txs)?;
+
+        let mut records = BinaryFormat::new().records(Cursor::new(&buffer));
+        let read = records.header()?.cloned().unwrap();
+        assert_eq!(read.producer, "core-banking 4.2");
+        assert_eq!(read.created, header.created);
+        assert_eq!(read.record_count, Some(2));
+        assert!(!read.trailer);
+        assert_eq!(records.collect::<ParseResult<Vec<_>>>()?, txs);
+
+        // Обрезка по границе записи видна по числу записей в заголовке
+        let mut last = Vec::new();
+        BinaryFormat::new().write_to(&mut last, &txs[1..])?;
+        let truncated = &buffer[..buffer.len() - last.len()];
+        assert!(matches!(
+            BinaryFormat::new().read_from(Cursor::new(truncated)),
+            Err(ParseError::RecordCountMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+
+        // Файл без записей отличается от пустого и читается
+        let mut empty = Vec::new();
+        format.write_to(&mut empty, &[])?;
+        assert_eq!(BinaryFormat::new().read_from(Cursor::new(empty))?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_streamed_header_with_trailer() -> ParseResult<()> {
+        let txs = vec![Transaction::deposit(2, 100).tx_id(1).build()?];
+        let format = BinaryFormat::new()
+            .with_header(FileHeader::new("stream"))
+            .with_trailer(true);
+        let mut buffer = Vec::new();
+        format.write_records(&mut buffer, txs.clone().into_iter().map(Ok))?;
+
+        let mut records = BinaryFormat::new().records(Cursor::new(&buffer));
+        let header = records.header()?.cloned().unwrap();
+        assert_eq!(header.record_count, None);
+        assert!(header.trailer);
+        assert_eq!(records.collect::<ParseResult<Vec<_>>>()?, txs);
+
+        // Заголовок обещает итог, поэтому файл без него считается обрезанным
+        let without_trailer = &buffer[..buffer.len() - (4 + 4 + TRAILER_SIZE as usize)];
+        assert!(matches!(
+            BinaryFormat::new().read_from(Cursor::new(without_trailer)),
+            Err(ParseError::MissingTrailer)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_frame_too_large() -> ParseResult<()> {
+        let tx = Transaction::deposit(2, 100).tx_id(1).build()?;
+        let mut buffer = Vec::new();
+        BinaryFormat::new().write_to(&mut buffer, std::slice::from_ref(&tx))?;
+        let size = buffer.len() - 8;
+
+        let limited = BinaryFormat::new().with_max_frame_size(size);
+        assert_eq!(limited.read_from(Cursor::new(&buffer))?, vec![tx]);
+
+        // Испорченный размер отвергается по одному префиксу, без тела
+        let mut parser = BinaryFormat::new().push_parser();
+        let mut out = Vec::new();
+        let prefix = [&MAGIC[..], &u32::MAX.to_be_bytes()].concat();
+        assert!(matches!(
+            parser.feed_into(&prefix, &mut out),
+            Err(ParseError::FrameTooLarge {
+                max: DEFAULT_MAX_FRAME_SIZE,
+                ..
+            })
+        ));
+
+        let result = BinaryFormat::new()
+            .with_max_frame_size(size - 1)
+            .read_from(Cursor::new(&buffer));
+        assert!(matches!(result, Err(ParseError::FrameTooLarge { .. })));
+        Ok(())
+    }
 }
@@ -0,0 +1,169 @@
+use super::binary::{BinaryFormat, DEFAULT_MAX_FRAME_SIZE, MAGIC};
+use crate::{ParseError, ParseResult, Transaction};
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Магическое число и u32 размер тела
+const FRAME_PREFIX: usize = 4 + 4;
+
+/// Кодек tokio-util для передачи транзакций в формате YPBankBin (фича `codec`)
+///
+/// Каждая транзакция — отдельный кадр: магическое число, u32 размер и тело
+/// записи, как в файле. Заголовок и итог относятся к файлу целиком и в
+/// потоке кадров не передаются: кадр с другим магическим числом — ошибка.
+/// Размер тела ограничен, чтобы испорченный или чужой поток не заставил
+/// выделить память под заявленный размер.
+#[derive(Debug, Clone)]
+pub struct BinaryCodec {
+    format: BinaryFormat,
+    max_frame_size: usize,
+}
+
+impl Default for BinaryCodec {
+    fn default() -> Self {
+        BinaryCodec {
+            format: BinaryFormat::default(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl BinaryCodec {
+    /// Создаёт кодек с пределом кадра 1 МиБ
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Задаёт настройки разбора записей, например политику неизвестных кодов
+    pub fn with_format(mut self, format: BinaryFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Задаёт предел размера тела записи в байтах
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Проверяет размер тела записи
+    fn check_size(&self, size: usize) -> ParseResult<()> {
+        if size > self.max_frame_size {
+            return Err(ParseError::FrameTooLarge {
+                size,
+                max: self.max_frame_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for BinaryCodec {
+    type Item = Transaction;
+    type Error = ParseError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> ParseResult<Option<Transaction>> {
+        if src.len() >= MAGIC.len() && src[..MAGIC.len()] != MAGIC {
+            return Err(ParseError::InvalidMagic);
+        }
+        if src.len() < FRAME_PREFIX {
+            return Ok(None);
+        }
+
+        let size = u32::from_be_bytes([src[4], src[5], src[6], src[7]]) as usize;
+        self.check_size(size)?;
+        if src.len() < FRAME_PREFIX + size {
+            src.reserve(FRAME_PREFIX + size - src.len());
+            return Ok(None);
+        }
+
+        src.advance(FRAME_PREFIX);
+        let body = src.split_to(size);
+        self.format.decode_ref(&body)?.to_transaction().map(Some)
+    }
+}
+
+impl Encoder<&Transaction> for BinaryCodec {
+    type Error = ParseError;
+
+    fn encode(&mut self, transaction: &Transaction, dst: &mut BytesMut) -> ParseResult<()> {
+        let start = dst.len();
+        self.format
+            .write_transaction(&mut dst.writer(), transaction)?;
+        if let Err(e) = self.check_size(dst.len() - start - FRAME_PREFIX) {
+            dst.truncate(start);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+impl Encoder<Transaction> for BinaryCodec {
+    type Error = ParseError;
+
+    fn encode(&mut self, transaction: Transaction, dst: &mut BytesMut) -> ParseResult<()> {
+        self.encode(&transaction, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(description: &str) -> ParseResult<Transaction> {
+        Transaction::deposit(1, 1000)
+            .tx_id(1)
+            .timestamp(100)
+            .description(description)
+            .build()
+    }
+
+    #[test]
+    fn test_codec_partial_frames() -> ParseResult<()> {
+        let first = transaction("первая")?;
+        let second = transaction("вторая")?;
+        let mut encoded = BytesMut::new();
+        BinaryCodec::new().encode(&first, &mut encoded)?;
+        BinaryCodec::new().encode(&second, &mut encoded)?;
+
+        // Данные приходят по одному байту
+        let mut codec = BinaryCodec::new();
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        for byte in encoded {
+            src.put_u8(byte);
+            if let Some(transaction) = codec.decode(&mut src)? {
+                decoded.push(transaction);
+            }
+        }
+        assert_eq!(decoded, vec![first, second]);
+        assert!(src.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_codec_limits_and_magic() -> ParseResult<()> {
+        let long = transaction(&"x".repeat(100))?;
+        let mut codec = BinaryCodec::new().with_max_frame_size(64);
+        let mut dst = BytesMut::new();
+        let result = codec.encode(&long, &mut dst);
+        assert!(matches!(
+            result,
+            Err(ParseError::FrameTooLarge { max: 64, .. })
+        ));
+        assert!(dst.is_empty());
+
+        BinaryCodec::new().encode(&long, &mut dst)?;
+        dst.truncate(FRAME_PREFIX);
+        let result = codec.decode(&mut dst);
+        assert!(matches!(
+            result,
+            Err(ParseError::FrameTooLarge { max: 64, .. })
+        ));
+
+        let mut src = BytesMut::from(&b"YPBH"[..]);
+        let result = codec.decode(&mut src);
+        assert!(matches!(result, Err(ParseError::InvalidMagic)));
+        Ok(())
+    }
+}
@@ -1,31 +1,168 @@
-use super::Format;
-use crate::{ParseError, ParseResult, Transaction, TransactionStatus, TransactionType};
-use std::io::{BufRead, BufReader, Read, Write};
+use super::binary::Entry;
+use super::parallel;
+use super::push::{LineBuffer, PushParser, PushRecords, line_str};
+use super::trailer::{Totals, TrailerCheck, TrailerPolicy};
+use super::{Format, METADATA_PREFIX, check_description, check_metadata};
+use crate::{
+    ParseError, ParseResult, Transaction, TransactionStatus, TransactionType, UnknownCodePolicy,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
 
 const EXPECTED_HEADER: &str =
     "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION";
+const PARENT_TX_ID_COLUMN: &str = "PARENT_TX_ID";
+/// Первое поле итоговой строки `TOTAL,<число>,<сумма>`
+const TRAILER_FIELD: &str = "TOTAL";
 
 /// Парсер для CSV формата YPBankCsv
-pub struct CsvFormat;
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CsvFormat {
+    unknown_codes: UnknownCodePolicy,
+    trailer: bool,
+    trailer_policy: TrailerPolicy,
+}
 
 impl CsvFormat {
-    /// Парсит строку CSV
-    fn parse_csv_line(line: &str) -> ParseResult<Transaction> {
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() != 8 {
+    /// Создаёт парсер с настройками по умолчанию
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Задаёт политику для неизвестных кодов типа и статуса
+    pub fn with_unknown_codes(mut self, policy: UnknownCodePolicy) -> Self {
+        self.unknown_codes = policy;
+        self
+    }
+
+    /// Дописывать ли в конец строку с числом записей и суммой
+    pub fn with_trailer(mut self, trailer: bool) -> Self {
+        self.trailer = trailer;
+        self
+    }
+
+    /// Задаёт, обязательна ли итоговая строка при чтении
+    pub fn with_trailer_policy(mut self, policy: TrailerPolicy) -> Self {
+        self.trailer_policy = policy;
+        self
+    }
+
+    /// Читает буфер с данными в `threads` потоков
+    ///
+    /// Описание не может содержать перевод строки, поэтому каждый перевод
+    /// строки — граница записи, и буфер делится на куски по ним. Куски
+    /// разбираются параллельно, транзакции возвращаются в порядке файла,
+    /// итоговая строка сверяется так же, как при потоковом чтении.
+    pub fn read_parallel(&self, data: &[u8], threads: usize) -> ParseResult<Vec<Transaction>> {
+        if data.is_empty() {
+            return Err(ParseError::InvalidFormat(
+                "Отсутствует заголовок CSV".to_string(),
+            ));
+        }
+        let (header, rest) = match data.iter().position(|&b| b == b'\n') {
+            Some(end) => data.split_at(end + 1),
+            None => (data, &[][..]),
+        };
+        let columns = Columns::from_header(line_str(header)?.trim())?;
+
+        let chunks = parallel::split_lines(rest, parallel::chunk_count(rest.len(), threads));
+        let mut trailer = TrailerCheck::new(self.trailer_policy);
+        let transactions = parallel::decode_chunks(&chunks, &mut trailer, |chunk, entries| {
+            for line in chunk.split(|&b| b == b'\n') {
+                let line = line_str(line)?.trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                let fields = split_fields(line)?;
+                entries.push(if fields[0] == TRAILER_FIELD {
+                    Entry::Trailer(parse_trailer(&fields)?)
+                } else {
+                    Entry::Transaction(self.parse_csv_line(&fields, &columns)?)
+                });
+            }
+            Ok(())
+        })?;
+
+        trailer.finish()?;
+        Ok(transactions)
+    }
+
+    /// Записывает поток транзакций с заранее известным набором колонок
+    ///
+    /// В отличие от [`Format::write_records`], транзакции не собираются в
+    /// память: заголовок берётся из `columns`, а строки пишутся по мере
+    /// поступления. Транзакция с `PARENT_TX_ID` или ключом метаданных, для
+    /// которых в `columns` нет колонки, — ошибка; записанное до неё остаётся
+    /// в `writer`.
+    pub fn write_records_with_columns<W, I>(
+        &self,
+        mut writer: W,
+        columns: &CsvColumns,
+        records: I,
+    ) -> ParseResult<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = ParseResult<Transaction>>,
+    {
+        let layout = columns.layout();
+        writeln!(writer, "{}", layout.header())?;
+
+        let mut totals = Totals::default();
+        for transaction in records {
+            let transaction = transaction?;
+            check_description(&transaction)?;
+            check_metadata(&transaction)?;
+            if !columns.covers(&transaction) {
+                return Err(ParseError::InvalidFormat(format!(
+                    "Для транзакции {} нет колонок в заголовке CSV",
+                    transaction.tx_id
+                )));
+            }
+            writeln!(
+                writer,
+                "{}",
+                Self::format_transaction(&transaction, &layout)
+            )?;
+            totals.add(&transaction);
+        }
+        if self.trailer {
+            writeln!(writer, "{TRAILER_FIELD},{},{}", totals.count, totals.amount)?;
+        }
+
+        Ok(())
+    }
+
+    /// Парсит поля строки CSV
+    fn parse_csv_line(&self, parts: &[String], columns: &Columns) -> ParseResult<Transaction> {
+        if parts.len() != 8 + columns.len() {
             return Err(ParseError::InvalidFormat(
                 "Неверное количество полей в CSV".to_string(),
             ));
         }
 
         let tx_id = parts[0].parse()?;
-        let tx_type = TransactionType::try_from(parts[1])?;
+        let tx_type = TransactionType::from_name(&parts[1], self.unknown_codes)?;
         let from_user_id = parts[2].parse()?;
         let to_user_id = parts[3].parse()?;
         let amount = parts[4].parse()?;
         let timestamp = parts[5].parse()?;
-        let status = TransactionStatus::try_from(parts[6])?;
-        let description = parts[7].trim_matches('"').to_string();
+        let status = TransactionStatus::from_name(&parts[6], self.unknown_codes)?;
+        let description = parts[7].clone();
+
+        let mut parent_tx_id = None;
+        let mut metadata = BTreeMap::new();
+        for (column, value) in columns.0.iter().zip(&parts[8..]) {
+            if value.is_empty() {
+                continue;
+            }
+            match column {
+                Column::ParentTxId => parent_tx_id = Some(value.parse()?),
+                Column::Metadata(key) => {
+                    metadata.insert(key.clone(), value.clone());
+                }
+            }
+        }
 
         Ok(Transaction {
             tx_id,
@@ -36,12 +173,14 @@ impl CsvFormat {
             timestamp,
             status,
             description,
+            parent_tx_id,
+            metadata,
         })
     }
 
     /// Форматирует транзакцию в CSV строку
-    fn format_transaction(transaction: &Transaction) -> String {
-        format!(
+    fn format_transaction(transaction: &Transaction, columns: &Columns) -> String {
+        let mut line = format!(
             "{tx_id},{tx_type},{from_user_id},{to_user_id},{amount},{timestamp},{status},\"{description}\"",
             tx_id = transaction.tx_id,
             tx_type = transaction.tx_type,
@@ -50,49 +189,318 @@ impl CsvFormat {
             amount = transaction.amount,
             timestamp = transaction.timestamp,
             status = transaction.status,
-            description = transaction.description,
-        )
+            description = transaction.description.replace('"', "\"\""),
+        );
+
+        for column in &columns.0 {
+            line.push(',');
+            match column {
+                Column::ParentTxId => {
+                    if let Some(parent_tx_id) = transaction.parent_tx_id {
+                        line.push_str(&parent_tx_id.to_string());
+                    }
+                }
+                Column::Metadata(key) => {
+                    if let Some(value) = transaction.metadata.get(key) {
+                        line.push('"');
+                        line.push_str(&value.replace('"', "\"\""));
+                        line.push('"');
+                    }
+                }
+            }
+        }
+
+        line
     }
 }
 
-impl Format for CsvFormat {
-    fn read_from<R: Read>(&self, reader: R) -> ParseResult<Vec<Transaction>> {
-        let mut transactions = Vec::new();
-        let buf_reader = BufReader::new(reader);
-        let mut lines = buf_reader.lines();
-
-        if let Some(header_line) = lines.next() {
-            let header = header_line?;
-            if header.trim() != EXPECTED_HEADER {
+/// Разбирает итоговую строку `TOTAL,<число>,<сумма>`
+fn parse_trailer(fields: &[String]) -> ParseResult<Totals> {
+    match fields {
+        [_, count, amount] => Ok(Totals {
+            count: count.parse()?,
+            amount: amount.parse()?,
+        }),
+        _ => Err(ParseError::InvalidFormat(
+            "Неверное количество полей в итоговой строке CSV".to_string(),
+        )),
+    }
+}
+
+/// Делит строку CSV на поля
+///
+/// Поле в кавычках может содержать запятые, а кавычка внутри него
+/// удваивается: `"say ""hi"", then go"`. Кавычки вокруг поля снимаются.
+fn split_fields(line: &str) -> ParseResult<Vec<String>> {
+    let invalid = || ParseError::InvalidFormat("Незакрытая кавычка в строке CSV".to_string());
+
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next().ok_or_else(invalid)? {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' => break,
+                    c => field.push(c),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
                 return Err(ParseError::InvalidFormat(
-                    "Неверный формат заголовка CSV".to_string(),
+                    "Символы после закрывающей кавычки в строке CSV".to_string(),
                 ));
             }
-        } else {
+        }
+        while let Some(c) = chars.next_if(|&c| c != ',') {
+            field.push(c);
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+/// Набор необязательных колонок CSV для потоковой записи
+///
+/// Собирается по транзакциям через [`CsvColumns::add`], например пока
+/// поток читается для сортировки, и затем передаётся в
+/// [`CsvFormat::write_records_with_columns`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CsvColumns {
+    parent_tx_id: bool,
+    metadata: BTreeSet<String>,
+}
+
+impl CsvColumns {
+    /// Пустой набор: только обязательные колонки
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Добавляет колонки, нужные для записи транзакции
+    pub fn add(&mut self, transaction: &Transaction) {
+        self.parent_tx_id |= transaction.parent_tx_id.is_some();
+        for key in transaction.metadata.keys() {
+            self.metadata.insert(key.clone());
+        }
+    }
+
+    /// Есть ли колонки для всех полей транзакции
+    fn covers(&self, transaction: &Transaction) -> bool {
+        (self.parent_tx_id || transaction.parent_tx_id.is_none())
+            && transaction
+                .metadata
+                .keys()
+                .all(|key| self.metadata.contains(key))
+    }
+
+    /// Колонки в порядке заголовка: `PARENT_TX_ID`, затем метаданные по ключам
+    fn layout(&self) -> Columns {
+        let mut columns = Vec::new();
+        if self.parent_tx_id {
+            columns.push(Column::ParentTxId);
+        }
+        columns.extend(self.metadata.iter().cloned().map(Column::Metadata));
+        Columns(columns)
+    }
+}
+
+/// Необязательная колонка, следующая за DESCRIPTION
+#[derive(Debug, PartialEq)]
+enum Column {
+    ParentTxId,
+    /// Колонка `META_<KEY>` с значением метаданных; пустая ячейка — ключа нет
+    Metadata(String),
+}
+
+/// Необязательные колонки в порядке заголовка
+#[derive(Debug, Default)]
+struct Columns(Vec<Column>);
+
+impl Columns {
+    /// Определяет набор колонок по строке заголовка
+    fn from_header(header: &str) -> ParseResult<Self> {
+        let invalid = || ParseError::InvalidFormat("Неверный формат заголовка CSV".to_string());
+
+        let rest = header.strip_prefix(EXPECTED_HEADER).ok_or_else(invalid)?;
+        if !rest.is_empty() && !rest.starts_with(',') {
+            return Err(invalid());
+        }
+
+        let mut columns = Vec::new();
+        for name in rest.split(',').skip(1) {
+            let column = match name.strip_prefix(METADATA_PREFIX) {
+                Some(key) if !key.is_empty() => Column::Metadata(key.to_string()),
+                None if name == PARENT_TX_ID_COLUMN => Column::ParentTxId,
+                _ => return Err(invalid()),
+            };
+            if columns.contains(&column) {
+                return Err(invalid());
+            }
+            columns.push(column);
+        }
+
+        Ok(Columns(columns))
+    }
+
+    /// Выбирает колонки, необходимые для записи транзакций
+    fn for_transactions(transactions: &[Transaction]) -> Self {
+        let mut columns = CsvColumns::new();
+        for transaction in transactions {
+            columns.add(transaction);
+        }
+        columns.layout()
+    }
+
+    /// Число необязательных колонок
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Строка заголовка с необязательными колонками
+    fn header(&self) -> String {
+        let mut header = EXPECTED_HEADER.to_string();
+        for column in &self.0 {
+            header.push(',');
+            match column {
+                Column::ParentTxId => header.push_str(PARENT_TX_ID_COLUMN),
+                Column::Metadata(key) => {
+                    header.push_str(METADATA_PREFIX);
+                    header.push_str(key);
+                }
+            }
+        }
+        header
+    }
+}
+
+/// Построчный разбор YPBankCsv
+///
+/// Основа [`CsvPushParser`]: первая строка — заголовок, пустые строки
+/// пропускаются, итоговая строка сверяется.
+pub(super) struct LineDecoder {
+    format: CsvFormat,
+    /// Колонки заголовка; `None`, пока заголовок не прочитан
+    columns: Option<Columns>,
+    trailer: TrailerCheck,
+}
+
+impl LineDecoder {
+    pub(super) fn new(format: CsvFormat) -> Self {
+        LineDecoder {
+            format,
+            columns: None,
+            trailer: TrailerCheck::new(format.trailer_policy),
+        }
+    }
+
+    /// Разбирает строку без перевода строки
+    pub(super) fn decode(&mut self, line: &str) -> ParseResult<Option<Transaction>> {
+        let line = line.trim();
+        let Some(columns) = &self.columns else {
+            self.columns = Some(Columns::from_header(line)?);
+            return Ok(None);
+        };
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let fields = split_fields(line)?;
+        if fields[0] == TRAILER_FIELD {
+            self.trailer.trailer(parse_trailer(&fields)?)?;
+            return Ok(None);
+        }
+        let transaction = self.format.parse_csv_line(&fields, columns)?;
+        self.trailer.record(transaction.tx_id, transaction.amount)?;
+        Ok(Some(transaction))
+    }
+
+    /// Проверяет источник после последней строки
+    pub(super) fn finish(&self) -> ParseResult<()> {
+        if self.columns.is_none() {
             return Err(ParseError::InvalidFormat(
                 "Отсутствует заголовок CSV".to_string(),
             ));
         }
+        self.trailer.finish()
+    }
+}
 
-        for line in lines {
-            let line = line?;
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
+/// Инкрементальный разбор YPBankCsv
+///
+/// Копит незавершённую строку между порциями. Создаётся
+/// [`Format::push_parser`].
+pub struct CsvPushParser {
+    lines: LineBuffer,
+    decoder: LineDecoder,
+}
 
-            let transaction = Self::parse_csv_line(line)?;
-            transactions.push(transaction);
-        }
+impl PushParser for CsvPushParser {
+    fn feed_into(&mut self, data: &[u8], out: &mut Vec<Transaction>) -> ParseResult<()> {
+        let decoder = &mut self.decoder;
+        self.lines.feed(data, |line| {
+            out.extend(decoder.decode(line)?);
+            Ok(())
+        })
+    }
 
-        Ok(transactions)
+    fn finish_into(&mut self, out: &mut Vec<Transaction>) -> ParseResult<()> {
+        let decoder = &mut self.decoder;
+        self.lines.finish(|line| {
+            out.extend(decoder.decode(line)?);
+            Ok(())
+        })?;
+        self.decoder.finish()
     }
+}
 
+/// Потоковый читатель формата YPBankCsv
+pub type CsvRecords<R> = PushRecords<R, CsvPushParser>;
+
+impl Format for CsvFormat {
+    type Records<R: Read> = CsvRecords<R>;
+    type Parser = CsvPushParser;
+
+    fn records<R: Read>(&self, reader: R) -> Self::Records<R> {
+        PushRecords::new(reader, self.push_parser())
+    }
+
+    fn push_parser(&self) -> Self::Parser {
+        CsvPushParser {
+            lines: LineBuffer::default(),
+            decoder: LineDecoder::new(*self),
+        }
+    }
+
+    /// Набор необязательных колонок определяется по всем транзакциям,
+    /// поэтому [`Format::write_records`] для CSV собирает их в память;
+    /// без этого можно писать через [`CsvFormat::write_records_with_columns`].
     fn write_to<W: Write>(&self, mut writer: W, transactions: &[Transaction]) -> ParseResult<()> {
-        writeln!(writer, "{}", EXPECTED_HEADER)?;
+        // Всё проверяется до заголовка, чтобы при ошибке ничего не записать
+        for transaction in transactions {
+            check_description(transaction)?;
+            check_metadata(transaction)?;
+        }
+        let columns = Columns::for_transactions(transactions);
+        writeln!(writer, "{}", columns.header())?;
 
+        let mut totals = Totals::default();
         for transaction in transactions {
-            writeln!(writer, "{}", Self::format_transaction(transaction))?;
+            writeln!(
+                writer,
+                "{}",
+                Self::format_transaction(transaction, &columns)
+            )?;
+            totals.add(transaction);
+        }
+        if self.trailer {
+            writeln!(writer, "{TRAILER_FIELD},{},{}", totals.count, totals.amount)?;
         }
 
         Ok(())
@@ -117,6 +525,8 @@ mod tests {
                 timestamp: 1672531200000,
                 status: TransactionStatus::Success,
                 description: "Initial account funding".to_string(),
+                parent_tx_id: None,
+                metadata: BTreeMap::new(),
             },
             Transaction {
                 tx_id: 1002,
@@ -127,10 +537,12 @@ mod tests {
                 timestamp: 1672534800000,
                 status: TransactionStatus::Failure,
                 description: "Payment for services".to_string(),
+                parent_tx_id: None,
+                metadata: BTreeMap::new(),
             },
         ];
 
-        let format = CsvFormat;
+        let format = CsvFormat::new();
         let mut buffer = Vec::new();
 
         format.write_to(&mut buffer, &transactions)?;
@@ -146,7 +558,7 @@ mod tests {
     #[test]
     fn test_csv_missing_header() {
         let data = "1001,DEPOSIT,0,501,50000,1672531200000,SUCCESS,\"test\"\n";
-        let format = CsvFormat;
+        let format = CsvFormat::new();
         let result = format.read_from(Cursor::new(data));
         assert!(result.is_err());
     }
@@ -155,7 +567,7 @@ mod tests {
     fn test_csv_invalid_number() {
         let data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
                     abc,DEPOSIT,0,501,50000,1672531200000,SUCCESS,\"test\"\n";
-        let format = CsvFormat;
+        let format = CsvFormat::new();
         let result = format.read_from(Cursor::new(data));
         assert!(matches!(result, Err(ParseError::ParseInt(_))));
     }
@@ -164,7 +576,7 @@ mod tests {
     fn test_csv_invalid_enum() {
         let data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
                     1001,INVALID,0,501,50000,1672531200000,SUCCESS,\"test\"\n";
-        let format = CsvFormat;
+        let format = CsvFormat::new();
         let result = format.read_from(Cursor::new(data));
         assert!(matches!(result, Err(ParseError::InvalidTransactionType(_))));
     }
@@ -175,9 +587,244 @@ mod tests {
                     \n\
                     1001,DEPOSIT,0,501,50000,1672531200000,SUCCESS,\"test\"\n\
                     \n";
-        let format = CsvFormat;
+        let format = CsvFormat::new();
         let txs = format.read_from(Cursor::new(data)).unwrap();
         assert_eq!(txs.len(), 1);
         assert_eq!(txs[0].tx_id, 1001);
     }
+
+    #[test]
+    fn test_csv_parent_tx_id_column() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(501, 50_000).tx_id(1001).build()?,
+            Transaction::builder()
+                .tx_id(1002)
+                .tx_type(TransactionType::Refund)
+                .from_user_id(502)
+                .to_user_id(501)
+                .amount(50_000)
+                .parent_tx_id(1001)
+                .build()?,
+        ];
+
+        let format = CsvFormat::new();
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &transactions)?;
+
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert!(text.starts_with(&format!("{EXPECTED_HEADER},PARENT_TX_ID\n")));
+        assert_eq!(format.read_from(Cursor::new(buffer))?, transactions);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_unknown_column() {
+        let data =
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION,EXTRA\n";
+        let result = CsvFormat::new().read_from(Cursor::new(data));
+        assert!(matches!(result, Err(ParseError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_csv_metadata_columns() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(501, 100)
+                .tx_id(1)
+                .metadata("channel", "atm")
+                .metadata("terminal_id", "T-17")
+                .build()?,
+            Transaction::withdrawal(501, 50)
+                .tx_id(2)
+                .metadata("mcc", "5411")
+                .build()?,
+        ];
+
+        let format = CsvFormat::new();
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &transactions)?;
+
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        let header = text.lines().next().unwrap();
+        assert_eq!(
+            header,
+            format!("{EXPECTED_HEADER},META_channel,META_mcc,META_terminal_id")
+        );
+        assert_eq!(format.read_from(Cursor::new(buffer))?, transactions);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_metadata_with_comma() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(501, 100)
+                .tx_id(1)
+                .timestamp(1)
+                .metadata("terminal", "Moscow, Tverskaya")
+                .metadata("note", "say \"hi\", then go")
+                .build()?,
+        ];
+        let mut buffer = Vec::new();
+        CsvFormat::new().write_to(&mut buffer, &transactions)?;
+        assert!(String::from_utf8(buffer.clone())?.contains("\"Moscow, Tverskaya\""));
+        assert_eq!(CsvFormat::new().read_from(buffer.as_slice())?, transactions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_write_records_with_columns() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(501, 100)
+                .tx_id(1)
+                .timestamp(1)
+                .metadata("mcc", "5411")
+                .build()?,
+            Transaction::withdrawal(501, 40)
+                .tx_id(2)
+                .timestamp(2)
+                .parent_tx_id(1)
+                .metadata("channel", "app")
+                .build()?,
+        ];
+        let format = CsvFormat::new().with_trailer(true);
+        let mut columns = CsvColumns::new();
+        transactions.iter().for_each(|t| columns.add(t));
+
+        let mut streamed = Vec::new();
+        format.write_records_with_columns(
+            &mut streamed,
+            &columns,
+            transactions.iter().cloned().map(Ok),
+        )?;
+        let mut collected = Vec::new();
+        format.write_to(&mut collected, &transactions)?;
+        assert_eq!(streamed, collected);
+
+        let mut partial = CsvColumns::new();
+        partial.add(&transactions[0]);
+        let result = format.write_records_with_columns(
+            Vec::new(),
+            &partial,
+            transactions.into_iter().map(Ok),
+        );
+        assert!(matches!(result, Err(ParseError::InvalidFormat(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_invalid_metadata_rejected_before_output() -> ParseResult<()> {
+        for (key, value) in [("", "x"), ("k", ""), ("a b", "x"), ("k", " x")] {
+            let result = Transaction::deposit(501, 100).metadata(key, value).build();
+            assert!(matches!(result, Err(ParseError::InvalidTransaction(_))));
+        }
+
+        // Ключ без значения у одной транзакции переживает чтение
+        let tagged = Transaction::deposit(501, 100)
+            .tx_id(1)
+            .timestamp(1)
+            .metadata("k", "v")
+            .build()?;
+        let plain = Transaction::deposit(502, 200)
+            .tx_id(2)
+            .timestamp(2)
+            .build()?;
+        let transactions = vec![tagged.clone(), plain];
+        let mut buffer = Vec::new();
+        CsvFormat::new().write_to(&mut buffer, &transactions)?;
+        assert_eq!(CsvFormat::new().read_from(buffer.as_slice())?, transactions);
+
+        // Транзакция, собранная в обход построителя, не даёт частичного вывода
+        let mut empty_value = tagged;
+        empty_value
+            .metadata
+            .insert("note".to_string(), String::new());
+        let mut buffer = Vec::new();
+        let result = CsvFormat::new().write_to(&mut buffer, &[empty_value.clone()]);
+        assert!(matches!(result, Err(ParseError::InvalidFormat(_))));
+        assert!(buffer.is_empty());
+        let result = crate::TextFormat::new().write_to(&mut buffer, &[empty_value]);
+        assert!(matches!(result, Err(ParseError::InvalidFormat(_))));
+        assert!(buffer.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_records_stream() {
+        let data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+                    1001,DEPOSIT,0,501,50000,1672531200000,SUCCESS,\"test\"\n\
+                    abc,DEPOSIT,0,501,50000,1672531200000,SUCCESS,\"test\"\n\
+                    1003,DEPOSIT,0,501,50000,1672531200000,SUCCESS,\"test\"\n";
+        let mut records = CsvFormat::new().records(Cursor::new(data));
+
+        assert_eq!(records.next().unwrap().unwrap().tx_id, 1001);
+        assert!(matches!(records.next(), Some(Err(ParseError::ParseInt(_)))));
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_csv_description_escaping() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(501, 100)
+                .description("Rent, March")
+                .build()?,
+            Transaction::deposit(501, 200)
+                .description(" say \"hi\", then go ")
+                .build()?,
+        ];
+        let format = CsvFormat::new();
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &transactions)?;
+
+        let written = String::from_utf8(buffer.clone()).unwrap();
+        assert!(written.ends_with(",\" say \"\"hi\"\", then go \"\n"));
+        assert_eq!(format.read_from(Cursor::new(buffer))?, transactions);
+
+        let tx = Transaction::deposit(501, 100)
+            .description("two\nlines")
+            .build()?;
+        let result = format.write_to(Vec::new(), &[tx]);
+        assert!(matches!(result, Err(ParseError::InvalidFormat(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_unclosed_quote() {
+        let data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+                    1001,DEPOSIT,0,501,50000,1672531200000,SUCCESS,\"test\n";
+        let result = CsvFormat::new().read_from(Cursor::new(data));
+        assert!(matches!(result, Err(ParseError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_csv_trailer() -> ParseResult<()> {
+        let txs = vec![
+            Transaction::deposit(501, 100).tx_id(1).build()?,
+            Transaction::deposit(501, 250).tx_id(2).build()?,
+        ];
+        let mut buffer = Vec::new();
+        CsvFormat::new()
+            .with_trailer(true)
+            .write_to(&mut buffer, &txs)?;
+        let written = String::from_utf8(buffer).unwrap();
+        assert!(written.ends_with("\nTOTAL,2,350\n"));
+
+        let required = CsvFormat::new().with_trailer_policy(TrailerPolicy::Require);
+        assert_eq!(required.read_from(Cursor::new(&written))?, txs);
+
+        let lines: Vec<&str> = written.lines().collect();
+        let truncated = lines[..2].join("\n");
+        assert!(matches!(
+            required.read_from(Cursor::new(&truncated)),
+            Err(ParseError::MissingTrailer)
+        ));
+        let missing_record = [lines[0], lines[1], lines[3]].join("\n");
+        assert!(matches!(
+            CsvFormat::new().read_from(Cursor::new(missing_record)),
+            Err(ParseError::TrailerMismatch { .. })
+        ));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,461 @@
+use super::binary::{
+    BinaryFormat, Entry, HEADER_MAGIC, MAGIC, TRAILER_MAGIC, TRAILER_SIZE, read_magic,
+};
+use crate::{ParseError, ParseResult, Transaction};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Индекс: `YPBI`, байт версии, u64 размер и u64 отпечаток файла данных,
+/// u32 размер блока, u64 число записей, для каждой записи u64 `tx_id` и u64
+/// смещение, затем для каждого блока u64 наименьший и u64 наибольший `timestamp`
+const INDEX_MAGIC: [u8; 4] = [0x59, 0x50, 0x42, 0x49];
+const INDEX_VERSION: u8 = 2;
+/// Байт с начала и с конца файла данных, по которым считается отпечаток
+const FINGERPRINT_SPAN: u64 = 4096;
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+/// Число записей в блоке с общим диапазоном времени
+const BLOCK_SIZE: u32 = 4096;
+/// Байты тела записи от начала до конца `timestamp`:
+/// tx_id, тип, отправитель, получатель, сумма, время
+const KEY_FIELDS_SIZE: u32 = 8 + 1 + 8 + 8 + 8 + 8;
+
+/// Индекс файла YPBankBin для произвольного доступа
+///
+/// Хранит смещение каждой записи и её `tx_id`, а для блоков по 4096
+/// записей подряд — диапазон `timestamp`. Строится быстрым проходом,
+/// который читает только размер, `tx_id` и `timestamp` каждой записи, и
+/// сохраняется в отдельный файл рядом с данными (см. [`BinaryIndex::sidecar_path`]).
+/// В памяти занимает около 24 байт на запись.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryIndex {
+    /// Размер файла данных, по которому индекс был построен
+    data_len: u64,
+    /// Отпечаток начала и конца файла данных, см. [`fingerprint`]
+    fingerprint: u64,
+    block_size: u32,
+    /// `tx_id` и смещение каждой записи в порядке файла
+    records: Vec<(u64, u64)>,
+    /// Наименьший и наибольший `timestamp` каждого блока
+    blocks: Vec<(u64, u64)>,
+    /// Номера записей, упорядоченные по `tx_id`, а при равных — по порядку файла
+    by_tx_id: Vec<usize>,
+}
+
+impl BinaryIndex {
+    /// Строит индекс, читая файл данных от начала до конца
+    ///
+    /// Тела записей после `timestamp` не читаются, а пропускаются переходом.
+    pub fn build<R: Read + Seek>(mut reader: R) -> ParseResult<Self> {
+        let mut records = Vec::new();
+        let mut blocks: Vec<(u64, u64)> = Vec::new();
+        let mut offset = 0;
+
+        while let Some(magic) = read_magic(&mut reader)? {
+            let size = reader.read_u32::<BigEndian>()?;
+            match magic {
+                HEADER_MAGIC if offset == 0 => skip(&mut reader, size)?,
+                TRAILER_MAGIC if size == TRAILER_SIZE => skip(&mut reader, size)?,
+                TRAILER_MAGIC => return Err(ParseError::RecordSizeMismatch),
+                MAGIC => {
+                    if size < KEY_FIELDS_SIZE {
+                        return Err(ParseError::RecordSizeMismatch);
+                    }
+                    let tx_id = reader.read_u64::<BigEndian>()?;
+                    skip(&mut reader, 1 + 8 + 8 + 8)?;
+                    let timestamp = reader.read_u64::<BigEndian>()?;
+                    skip(&mut reader, size - KEY_FIELDS_SIZE)?;
+
+                    if records.len().is_multiple_of(BLOCK_SIZE as usize) {
+                        blocks.push((timestamp, timestamp));
+                    } else if let Some((min, max)) = blocks.last_mut() {
+                        *min = (*min).min(timestamp);
+                        *max = (*max).max(timestamp);
+                    }
+                    records.push((tx_id, offset));
+                }
+                HEADER_MAGIC => {
+                    return Err(ParseError::InvalidFormat(
+                        "Заголовок файла не в начале".to_string(),
+                    ));
+                }
+                _ => return Err(ParseError::InvalidMagic),
+            }
+            offset += 4 + 4 + u64::from(size);
+        }
+
+        // Переход за конец источника не ошибка, поэтому обрыв виден только по длине
+        if reader.seek(SeekFrom::End(0))? != offset {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let fingerprint = fingerprint(&mut reader, offset)?;
+        Ok(Self::new(offset, fingerprint, BLOCK_SIZE, records, blocks))
+    }
+
+    fn new(
+        data_len: u64,
+        fingerprint: u64,
+        block_size: u32,
+        records: Vec<(u64, u64)>,
+        blocks: Vec<(u64, u64)>,
+    ) -> Self {
+        let mut by_tx_id: Vec<usize> = (0..records.len()).collect();
+        by_tx_id.sort_by_key(|&n| records[n].0);
+        BinaryIndex {
+            data_len,
+            fingerprint,
+            block_size,
+            records,
+            blocks,
+            by_tx_id,
+        }
+    }
+
+    /// Путь индекса для файла данных: `day.bin` → `day.bin.idx`
+    pub fn sidecar_path(data: &Path) -> PathBuf {
+        let mut path = data.as_os_str().to_owned();
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    /// Читает сохранённый индекс
+    pub fn read_from<R: Read>(mut reader: R) -> ParseResult<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != INDEX_MAGIC {
+            return Err(ParseError::InvalidMagic);
+        }
+        let version = reader.read_u8()?;
+        if version != INDEX_VERSION {
+            return Err(ParseError::InvalidFormat(format!(
+                "Неподдерживаемая версия индекса: {version}"
+            )));
+        }
+        let data_len = reader.read_u64::<BigEndian>()?;
+        let fingerprint = reader.read_u64::<BigEndian>()?;
+        let block_size = reader.read_u32::<BigEndian>()?;
+        if block_size == 0 {
+            return Err(ParseError::InvalidFormat(
+                "Нулевой размер блока индекса".to_string(),
+            ));
+        }
+        let count = reader.read_u64::<BigEndian>()?;
+
+        let mut records = Vec::new();
+        for _ in 0..count {
+            let tx_id = reader.read_u64::<BigEndian>()?;
+            let offset = reader.read_u64::<BigEndian>()?;
+            records.push((tx_id, offset));
+        }
+        let mut blocks = Vec::new();
+        for _ in 0..count.div_ceil(u64::from(block_size)) {
+            let min = reader.read_u64::<BigEndian>()?;
+            let max = reader.read_u64::<BigEndian>()?;
+            blocks.push((min, max));
+        }
+
+        Ok(Self::new(
+            data_len,
+            fingerprint,
+            block_size,
+            records,
+            blocks,
+        ))
+    }
+
+    /// Сохраняет индекс
+    pub fn write_to<W: Write>(&self, mut writer: W) -> ParseResult<()> {
+        writer.write_all(&INDEX_MAGIC)?;
+        writer.write_u8(INDEX_VERSION)?;
+        writer.write_u64::<BigEndian>(self.data_len)?;
+        writer.write_u64::<BigEndian>(self.fingerprint)?;
+        writer.write_u32::<BigEndian>(self.block_size)?;
+        writer.write_u64::<BigEndian>(self.records.len() as u64)?;
+        for &(tx_id, offset) in &self.records {
+            writer.write_u64::<BigEndian>(tx_id)?;
+            writer.write_u64::<BigEndian>(offset)?;
+        }
+        for &(min, max) in &self.blocks {
+            writer.write_u64::<BigEndian>(min)?;
+            writer.write_u64::<BigEndian>(max)?;
+        }
+        Ok(())
+    }
+
+    /// Число записей в файле данных
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Номер первой в порядке файла записи с данным `tx_id`
+    pub fn position(&self, tx_id: u64) -> Option<usize> {
+        let first = self
+            .by_tx_id
+            .partition_point(|&n| self.records[n].0 < tx_id);
+        self.by_tx_id
+            .get(first)
+            .copied()
+            .filter(|&n| self.records[n].0 == tx_id)
+    }
+}
+
+/// Пропускает `len` байт источника
+fn skip<R: Seek>(reader: &mut R, len: u32) -> ParseResult<()> {
+    reader.seek_relative(i64::from(len))?;
+    Ok(())
+}
+
+/// Отпечаток файла данных длиной `len`: FNV-1a его начала и конца
+///
+/// В начале лежат заголовок и первые записи, в конце — итог и последние
+/// записи, поэтому перезаписанный файл того же размера обычно даёт другой
+/// отпечаток, а проверка при открытии не читает файл целиком.
+fn fingerprint<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<u64> {
+    let head = len.min(FINGERPRINT_SPAN);
+    let tail = len.saturating_sub(FINGERPRINT_SPAN).max(head);
+
+    let mut hash = FNV_OFFSET;
+    let mut buffer = Vec::new();
+    for (start, end) in [(0, head), (tail, len)] {
+        reader.seek(SeekFrom::Start(start))?;
+        buffer.clear();
+        reader.by_ref().take(end - start).read_to_end(&mut buffer)?;
+        if buffer.len() as u64 != end - start {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        for &byte in &buffer {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+    Ok(hash)
+}
+
+/// Чтение файла YPBankBin по индексу
+///
+/// Создаётся через [`BinaryFormat::indexed`].
+pub struct IndexedReader<R> {
+    format: BinaryFormat,
+    reader: R,
+    index: BinaryIndex,
+    /// Номер записи, с которой начинается текущая позиция источника
+    cursor: Option<usize>,
+}
+
+impl<R: Read + Seek> IndexedReader<R> {
+    pub(super) fn new(
+        format: BinaryFormat,
+        mut reader: R,
+        index: BinaryIndex,
+    ) -> ParseResult<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        if len != index.data_len || fingerprint(&mut reader, len)? != index.fingerprint {
+            return Err(ParseError::StaleIndex);
+        }
+        Ok(IndexedReader {
+            format,
+            reader,
+            index,
+            cursor: None,
+        })
+    }
+
+    /// Индекс, по которому идёт чтение
+    pub fn index(&self) -> &BinaryIndex {
+        &self.index
+    }
+
+    /// Транзакция с данным `tx_id`; при повторах — первая в порядке файла
+    pub fn get_by_id(&mut self, tx_id: u64) -> ParseResult<Option<Transaction>> {
+        match self.index.position(tx_id) {
+            Some(n) => self.read_at(n).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Транзакция с номером `n` от начала файла, начиная с нуля
+    pub fn get_nth(&mut self, n: usize) -> ParseResult<Option<Transaction>> {
+        if n >= self.index.len() {
+            return Ok(None);
+        }
+        self.read_at(n).map(Some)
+    }
+
+    /// Транзакции с `timestamp` от `since` до `until` включительно в порядке файла
+    ///
+    /// Блоки, диапазон времени которых не пересекается с запрошенным, не
+    /// читаются; файл не обязан быть упорядочен по времени.
+    pub fn range_by_timestamp(&mut self, since: u64, until: u64) -> TimestampRange<'_, R> {
+        TimestampRange {
+            reader: self,
+            since,
+            until,
+            next: 0,
+            done: false,
+        }
+    }
+
+    /// Читает запись с номером `n`, переходя к ней, если источник не там
+    fn read_at(&mut self, n: usize) -> ParseResult<Transaction> {
+        let (tx_id, offset) = self.index.records[n];
+        if self.cursor != Some(n) {
+            self.reader.seek(SeekFrom::Start(offset))?;
+        }
+        self.cursor = None;
+
+        let magic = read_magic(&mut self.reader)?.ok_or(ParseError::StaleIndex)?;
+        let transaction = match self.format.read_entry(&mut self.reader, magic)? {
+            Entry::Transaction(transaction) if transaction.tx_id == tx_id => transaction,
+            _ => return Err(ParseError::StaleIndex),
+        };
+        // Записи идут подряд, поэтому следующая начинается там, где кончилась эта
+        self.cursor = Some(n + 1);
+        Ok(transaction)
+    }
+}
+
+/// Итератор по транзакциям в диапазоне времени, см.
+/// [`IndexedReader::range_by_timestamp`]
+pub struct TimestampRange<'a, R> {
+    reader: &'a mut IndexedReader<R>,
+    since: u64,
+    until: u64,
+    /// Номер следующей проверяемой записи
+    next: usize,
+    done: bool,
+}
+
+impl<R: Read + Seek> TimestampRange<'_, R> {
+    fn next_record(&mut self) -> ParseResult<Option<Transaction>> {
+        let block_size = self.reader.index.block_size as usize;
+
+        while self.next < self.reader.index.len() {
+            if self.next.is_multiple_of(block_size) {
+                let (min, max) = self.reader.index.blocks[self.next / block_size];
+                if max < self.since || min > self.until {
+                    self.next += block_size;
+                    continue;
+                }
+            }
+
+            let n = self.next;
+            self.next += 1;
+            let transaction = self.reader.read_at(n)?;
+            if (self.since..=self.until).contains(&transaction.timestamp) {
+                return Ok(Some(transaction));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read + Seek> Iterator for TimestampRange<'_, R> {
+    type Item = ParseResult<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.next_record().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{FileHeader, Format};
+    use std::io::Cursor;
+
+    /// Транзакции с перемешанными tx_id и временем, чтобы блоки пересекались
+    fn sample(count: u64) -> ParseResult<Vec<Transaction>> {
+        (0..count)
+            .map(|i| {
+                Transaction::transfer(1 + i % 7, 100, 10 + i as i64)
+                    .tx_id((i * 7_919) % count)
+                    .timestamp(1_000_000 + (i % 5_000) * 10)
+                    .description(format!("record {i}"))
+                    .build()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_indexed_lookups() -> ParseResult<()> {
+        let transactions = sample(10_000)?;
+        let format = BinaryFormat::new()
+            .with_header(FileHeader::new("test"))
+            .with_trailer(true);
+        let mut data = Vec::new();
+        format.write_to(&mut data, &transactions)?;
+
+        let index = BinaryIndex::build(Cursor::new(&data))?;
+        assert_eq!(index.len(), transactions.len());
+        let mut saved = Vec::new();
+        index.write_to(&mut saved)?;
+        let index = BinaryIndex::read_from(Cursor::new(saved))?;
+
+        let mut reader = BinaryFormat::new().indexed(Cursor::new(&data), index)?;
+        assert_eq!(reader.get_nth(0)?.as_ref(), Some(&transactions[0]));
+        assert_eq!(reader.get_nth(9_999)?.as_ref(), Some(&transactions[9_999]));
+        assert_eq!(reader.get_nth(10_000)?, None);
+
+        let wanted = &transactions[4_321];
+        assert_eq!(reader.get_by_id(wanted.tx_id)?.as_ref(), Some(wanted));
+        assert_eq!(reader.get_by_id(10_000)?, None);
+
+        let expected: Vec<&Transaction> = transactions
+            .iter()
+            .filter(|t| (1_010_000..=1_010_500).contains(&t.timestamp))
+            .collect();
+        let found = reader
+            .range_by_timestamp(1_010_000, 1_010_500)
+            .collect::<ParseResult<Vec<_>>>()?;
+        assert_eq!(found.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(reader.range_by_timestamp(0, 999_999).count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_index() -> ParseResult<()> {
+        let transactions = sample(3)?;
+        let mut data = Vec::new();
+        BinaryFormat::new().write_to(&mut data, &transactions)?;
+        let index = BinaryIndex::build(Cursor::new(&data))?;
+
+        BinaryFormat::new().write_to(&mut data, &transactions[..1])?;
+        assert!(matches!(
+            BinaryFormat::new().indexed(Cursor::new(&data), index.clone()),
+            Err(ParseError::StaleIndex)
+        ));
+
+        // Файл того же размера с другим концом тоже не подходит к индексу
+        let mut data = Vec::new();
+        BinaryFormat::new().write_to(&mut data, &transactions)?;
+        *data.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            BinaryFormat::new().indexed(Cursor::new(&data), index),
+            Err(ParseError::StaleIndex)
+        ));
+
+        // Обрезанный файл не индексируется
+        data.truncate(data.len() - 1);
+        assert!(BinaryIndex::build(Cursor::new(&data)).is_err());
+
+        assert_eq!(
+            BinaryIndex::sidecar_path(Path::new("out/day.bin")),
+            Path::new("out/day.bin.idx")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+use super::binary::{
+    BinaryFormat, FileHeader, FrameDecoder, HEADER_MAGIC, split_entry, split_prefixed,
+};
+use crate::{ParseError, ParseResult, Transaction, TransactionStatus, TransactionType};
+use memmap2::Mmap;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+/// Файл YPBankBin, отображённый в память
+///
+/// Записи читаются прямо из отображения через [`MappedFile::records`],
+/// без копирования в буфер и без выделения строк на каждую запись.
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    /// Отображает файл в память только для чтения
+    ///
+    /// # Safety
+    ///
+    /// Файл не должен меняться и усекаться ни этим, ни другим процессом,
+    /// пока `MappedFile` и заимствованные из него записи живы. Иначе срезы,
+    /// выданные как неизменяемые, поменяются посреди разбора, а обращение к
+    /// усечённой части завершит процесс по SIGBUS.
+    pub unsafe fn open(path: impl AsRef<Path>) -> ParseResult<Self> {
+        let file = File::open(path)?;
+        // SAFETY: неизменность файла гарантирует вызывающий по контракту выше
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MappedFile { map })
+    }
+
+    /// Содержимое файла
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Записи файла без копирования
+    pub fn records(&self, format: &BinaryFormat) -> BorrowedRecords<'_> {
+        format.borrowed_records(&self.map)
+    }
+}
+
+/// Транзакция, заимствующая строки из буфера с данными YPBankBin
+///
+/// Числовые поля разбираются сразу, а описание и метаданные остаются
+/// байтами буфера: UTF-8 проверяется только при обращении к ним.
+/// [`TransactionRef::to_transaction`] копирует запись в [`Transaction`].
+#[derive(Debug, Clone)]
+pub struct TransactionRef<'a> {
+    pub tx_id: u64,
+    pub tx_type: TransactionType,
+    pub from_user_id: u64,
+    pub to_user_id: u64,
+    pub amount: i64,
+    pub timestamp: u64,
+    pub status: TransactionStatus,
+    pub parent_tx_id: Option<u64>,
+    pub(super) description: &'a [u8],
+    pub(super) metadata: MetadataRefs<'a>,
+}
+
+impl<'a> TransactionRef<'a> {
+    /// Описание; UTF-8 проверяется при каждом вызове
+    pub fn description(&self) -> ParseResult<&'a str> {
+        Ok(std::str::from_utf8(self.description)?)
+    }
+
+    /// Описание без проверки UTF-8
+    pub fn description_bytes(&self) -> &'a [u8] {
+        self.description
+    }
+
+    /// Пары метаданных в порядке записи
+    pub fn metadata(&self) -> MetadataRefs<'a> {
+        self.metadata.clone()
+    }
+
+    /// Копирует запись в owned-транзакцию
+    pub fn to_transaction(&self) -> ParseResult<Transaction> {
+        let mut metadata = BTreeMap::new();
+        let mut pairs = self.metadata.clone();
+        while let Some(pair) = pairs.next_raw() {
+            let (key, value) = pair?;
+            metadata.insert(
+                String::from_utf8(key.to_vec())?,
+                String::from_utf8(value.to_vec())?,
+            );
+        }
+
+        Ok(Transaction {
+            tx_id: self.tx_id,
+            tx_type: self.tx_type,
+            from_user_id: self.from_user_id,
+            to_user_id: self.to_user_id,
+            amount: self.amount,
+            timestamp: self.timestamp,
+            status: self.status,
+            description: String::from_utf8(self.description.to_vec())?,
+            parent_tx_id: self.parent_tx_id,
+            metadata,
+        })
+    }
+}
+
+/// Итератор по парам метаданных записи без копирования
+#[derive(Debug, Clone, Default)]
+pub struct MetadataRefs<'a> {
+    rest: &'a [u8],
+    remaining: u32,
+    /// После пар не должно остаться байтов: в записи нет полей с
+    /// неизвестными флагами
+    strict: bool,
+}
+
+impl<'a> MetadataRefs<'a> {
+    pub(super) fn new(rest: &'a [u8], remaining: u32, strict: bool) -> Self {
+        MetadataRefs {
+            rest,
+            remaining,
+            strict,
+        }
+    }
+
+    /// Следующая пара байтов ключа и значения
+    fn next_raw(&mut self) -> Option<ParseResult<(&'a [u8], &'a [u8])>> {
+        if self.remaining == 0 {
+            if self.strict && !self.rest.is_empty() {
+                self.rest = &[];
+                return Some(Err(ParseError::RecordSizeMismatch));
+            }
+            return None;
+        }
+
+        self.remaining -= 1;
+        let pair = split_prefixed(&mut self.rest)
+            .and_then(|key| Ok((key, split_prefixed(&mut self.rest)?)));
+        if pair.is_err() {
+            self.remaining = 0;
+            self.rest = &[];
+        }
+        Some(pair)
+    }
+}
+
+impl<'a> Iterator for MetadataRefs<'a> {
+    type Item = ParseResult<(&'a str, &'a str)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_raw().map(|pair| {
+            let (key, value) = pair?;
+            Ok((std::str::from_utf8(key)?, std::str::from_utf8(value)?))
+        })
+    }
+}
+
+/// Читатель YPBankBin по буферу в памяти, возвращающий [`TransactionRef`]
+///
+/// Заголовок, итог и число записей проверяются так же, как в
+/// [`super::BinaryRecords`]. Создаётся [`BinaryFormat::borrowed_records`]
+/// или [`MappedFile::records`].
+pub struct BorrowedRecords<'a> {
+    data: &'a [u8],
+    decoder: FrameDecoder,
+    /// Заголовок уже искался
+    started: bool,
+    done: bool,
+}
+
+impl<'a> BorrowedRecords<'a> {
+    pub(super) fn new(decoder: FrameDecoder, data: &'a [u8]) -> Self {
+        BorrowedRecords {
+            data,
+            decoder,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Заголовок файла, если он есть
+    pub fn header(&mut self) -> ParseResult<Option<&FileHeader>> {
+        if let Err(e) = self.start() {
+            self.done = true;
+            return Err(e);
+        }
+        Ok(self.decoder.header())
+    }
+
+    /// Читает заголовок, если буфер с него начинается
+    fn start(&mut self) -> ParseResult<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+
+        if self.data.starts_with(&HEADER_MAGIC) {
+            let (magic, body) = self.take_entry()?;
+            self.decoder.decode(magic, body)?;
+        }
+        Ok(())
+    }
+
+    /// Отделяет от буфера магическое число и тело очередного элемента
+    fn take_entry(&mut self) -> ParseResult<([u8; 4], &'a [u8])> {
+        let (magic, body, rest) = split_entry(self.data)?;
+        self.data = rest;
+        Ok((magic, body))
+    }
+
+    /// Читает следующую транзакцию, сверяя итог, если он встретится
+    fn next_record(&mut self) -> ParseResult<Option<TransactionRef<'a>>> {
+        self.start()?;
+        while !self.data.is_empty() {
+            let (magic, body) = self.take_entry()?;
+            if let Some(transaction) = self.decoder.decode(magic, body)? {
+                return Ok(Some(transaction));
+            }
+        }
+        self.decoder.finish().map(|()| None)
+    }
+}
+
+impl<'a> Iterator for BorrowedRecords<'a> {
+    type Item = ParseResult<TransactionRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.next_record().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::Format;
+    use std::io::Write;
+
+    fn sample() -> ParseResult<Vec<Transaction>> {
+        Ok(vec![
+            Transaction::deposit(1, 1000)
+                .tx_id(1)
+                .timestamp(100)
+                .description("Пополнение")
+                .build()?,
+            Transaction::transfer(1, 2, 250)
+                .tx_id(2)
+                .timestamp(200)
+                .description("Перевод")
+                .parent_tx_id(1)
+                .metadata("channel", "app")
+                .metadata("mcc", "5411")
+                .build()?,
+        ])
+    }
+
+    #[test]
+    fn test_mapped_file_matches_streaming_reader() -> ParseResult<()> {
+        let transactions = sample()?;
+        let format = BinaryFormat::new()
+            .with_header(FileHeader::new("test"))
+            .with_trailer(true);
+        let mut file = tempfile::NamedTempFile::new()?;
+        format.write_to(&mut file, &transactions)?;
+        file.flush()?;
+
+        // SAFETY: временный файл больше никто не открывает
+        let mapped = unsafe { MappedFile::open(file.path())? };
+        let mut records = mapped.records(&format);
+        assert_eq!(records.header()?.map(|h| h.producer.as_str()), Some("test"));
+        let borrowed = records.collect::<ParseResult<Vec<_>>>()?;
+
+        assert_eq!(borrowed[1].description()?, "Перевод");
+        let metadata = borrowed[1].metadata().collect::<ParseResult<Vec<_>>>()?;
+        assert_eq!(metadata, vec![("channel", "app"), ("mcc", "5411")]);
+        let owned = borrowed
+            .iter()
+            .map(TransactionRef::to_transaction)
+            .collect::<ParseResult<Vec<_>>>()?;
+        assert_eq!(owned, transactions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_borrowed_records_lazy_description() -> ParseResult<()> {
+        let transaction = Transaction::deposit(1, 1000)
+            .tx_id(1)
+            .timestamp(100)
+            .description("ab")
+            .build()?;
+        let format = BinaryFormat::new();
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &[transaction])?;
+        // Портим последний байт описания: запись читается, ошибка — при обращении
+        let last = buffer.len() - 1;
+        buffer[last] = 0xFF;
+
+        let borrowed = format
+            .borrowed_records(&buffer)
+            .collect::<ParseResult<Vec<_>>>()?;
+        assert_eq!(borrowed[0].amount, 1000);
+        assert!(matches!(
+            borrowed[0].description(),
+            Err(ParseError::Utf8Str(_))
+        ));
+        assert!(matches!(
+            borrowed[0].to_transaction(),
+            Err(ParseError::Utf8(_))
+        ));
+
+        buffer.truncate(last);
+        let result = format.borrowed_records(&buffer).next();
+        assert!(matches!(result, Some(Err(ParseError::Io(_)))));
+        Ok(())
+    }
+}
@@ -1,18 +1,176 @@
+#[cfg(feature = "async")]
+mod async_io;
 mod binary;
+#[cfg(feature = "codec")]
+mod codec;
 mod csv;
+mod index;
+mod mapped;
+mod parallel;
+mod push;
 mod text;
+mod trailer;
 
-use crate::{ParseResult, Transaction};
+use crate::transaction::metadata_allowed;
+use crate::{ParseError, ParseResult, Transaction};
 use std::io::{Read, Write};
 
-pub use binary::BinaryFormat;
-pub use csv::CsvFormat;
-pub use text::TextFormat;
+#[cfg(feature = "async")]
+pub use async_io::{
+    AsyncBinaryRecords, AsyncCsvRecords, AsyncFormat, AsyncPushRecords, AsyncTextRecords,
+};
+pub use binary::{BinaryFormat, BinaryPushParser, BinaryRecords, FileHeader};
+#[cfg(feature = "codec")]
+pub use codec::BinaryCodec;
+pub use csv::{CsvColumns, CsvFormat, CsvPushParser, CsvRecords};
+pub use index::{BinaryIndex, IndexedReader, TimestampRange};
+pub use mapped::{BorrowedRecords, MappedFile, MetadataRefs, TransactionRef};
+pub use push::{PushParser, PushRecords};
+pub use text::{TextFormat, TextPushParser, TextRecords};
+pub use trailer::{Totals, TrailerPolicy};
 
 /// Общий трейт для всех форматов парсинга
 pub trait Format {
+    /// Итератор, читающий транзакции по одной
+    type Records<R: Read>: Iterator<Item = ParseResult<Transaction>>;
+    /// Инкрементальный парсер формата
+    type Parser: PushParser;
+
+    /// Читает транзакции потоком, не загружая весь источник в память.
+    /// После первой ошибки итератор завершается.
+    fn records<R: Read>(&self, reader: R) -> Self::Records<R>;
+
+    /// Создаёт парсер, которому байты передаются порциями, без ввода-вывода
+    fn push_parser(&self) -> Self::Parser;
+
     /// Читает транзакции из любого источника, реализующего Read
-    fn read_from<R: Read>(&self, reader: R) -> ParseResult<Vec<Transaction>>;
+    fn read_from<R: Read>(&self, reader: R) -> ParseResult<Vec<Transaction>> {
+        self.records(reader).collect()
+    }
     /// Записывает транзакции в любой приемник, реализующий Write
     fn write_to<W: Write>(&self, write: W, transactions: &[Transaction]) -> ParseResult<()>;
+
+    /// Записывает транзакции по мере поступления из итератора. При первой
+    /// ошибке в потоке запись прекращается и ошибка возвращается.
+    ///
+    /// Реализация по умолчанию собирает транзакции в память и передаёт их
+    /// в [`Format::write_to`]; потоковые форматы её переопределяют.
+    fn write_records<W, I>(&self, writer: W, records: I) -> ParseResult<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = ParseResult<Transaction>>,
+    {
+        let transactions = records.into_iter().collect::<ParseResult<Vec<_>>>()?;
+        self.write_to(writer, &transactions)
+    }
+}
+
+/// Формат данных, определённый по содержимому источника
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FormatKind {
+    Binary,
+    Csv,
+    Text,
+}
+
+impl FormatKind {
+    /// Определяет формат по первым байтам источника
+    ///
+    /// Бинарный формат узнаётся по магическому числу записи, заголовка или итога,
+    /// CSV — по заголовку, который начинается с `TX_ID,`, текстовый — по
+    /// первой значимой строке вида `КЛЮЧ: значение` или по итогу
+    /// `# TOTAL:`; пустые строки и прочие комментарии `#` пропускаются.
+    /// Для пустого или нераспознанного источника возвращается `None`.
+    pub fn detect(prefix: &[u8]) -> Option<Self> {
+        let binary_magics = [binary::MAGIC, binary::HEADER_MAGIC, binary::TRAILER_MAGIC];
+        if binary_magics.iter().any(|magic| prefix.starts_with(magic)) {
+            return Some(FormatKind::Binary);
+        }
+
+        let text = String::from_utf8_lossy(prefix);
+        let line = text.lines().map(str::trim).find(|line| {
+            !line.is_empty() && (!line.starts_with('#') || line.starts_with(text::TRAILER_PREFIX))
+        })?;
+        if line.starts_with(text::TRAILER_PREFIX) {
+            return Some(FormatKind::Text);
+        }
+
+        if line.starts_with("TX_ID,") {
+            Some(FormatKind::Csv)
+        } else if line.split_once(':').is_some_and(|(key, _)| {
+            !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        }) {
+            Some(FormatKind::Text)
+        } else {
+            None
+        }
+    }
+}
+
+/// Префикс ключей метаданных в текстовом и CSV форматах
+const METADATA_PREFIX: &str = "META_";
+
+/// Проверяет, что описание представимо в текстовом или CSV формате
+///
+/// Оба формата построчные, поэтому перевод строки в описании не
+/// пережил бы чтения; остальные символы экранируются при записи.
+fn check_description(transaction: &Transaction) -> ParseResult<()> {
+    if transaction.description.contains(['\n', '\r']) {
+        return Err(ParseError::InvalidFormat(format!(
+            "Описание транзакции {} с переводом строки нельзя записать в этом формате",
+            transaction.tx_id
+        )));
+    }
+    Ok(())
+}
+
+/// Проверяет, что метаданные представимы в текстовом или CSV формате
+///
+/// Правила для ключей и значений — в [`metadata_allowed`].
+fn check_metadata(transaction: &Transaction) -> ParseResult<()> {
+    for (key, value) in &transaction.metadata {
+        if !metadata_allowed(key, value) {
+            return Err(ParseError::InvalidFormat(format!(
+                "Метаданные {key:?}={value:?} транзакции {} нельзя записать в этом формате",
+                transaction.tx_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() -> ParseResult<()> {
+        let transactions = vec![Transaction::deposit(1, 100).tx_id(1).build()?];
+        let mut binary = Vec::new();
+        BinaryFormat::new().write_to(&mut binary, &transactions)?;
+        let mut csv = Vec::new();
+        CsvFormat::new().write_to(&mut csv, &transactions)?;
+        let mut text = "# Выгрузка\n\n".as_bytes().to_vec();
+        TextFormat::new().write_to(&mut text, &transactions)?;
+
+        assert_eq!(FormatKind::detect(&binary), Some(FormatKind::Binary));
+        assert_eq!(FormatKind::detect(&csv), Some(FormatKind::Csv));
+        assert_eq!(FormatKind::detect(&text), Some(FormatKind::Text));
+        assert_eq!(
+            FormatKind::detect(b"# TOTAL: COUNT=0, AMOUNT=0\n"),
+            Some(FormatKind::Text)
+        );
+        let mut empty_binary = Vec::new();
+        BinaryFormat::new()
+            .with_trailer(true)
+            .write_to(&mut empty_binary, &[])?;
+        assert_eq!(FormatKind::detect(&empty_binary), Some(FormatKind::Binary));
+        assert_eq!(FormatKind::detect(b""), None);
+        assert_eq!(FormatKind::detect(b"hello world"), None);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,151 @@
+use super::binary::Entry;
+use super::trailer::TrailerCheck;
+use crate::{ParseError, ParseResult, Transaction};
+use std::thread;
+
+/// Кусок меньше этого размера не стоит отдельного потока
+pub(super) const MIN_CHUNK: usize = 16 * 1024;
+
+/// Число кусков для буфера длиной `len`
+pub(super) fn chunk_count(len: usize, threads: usize) -> usize {
+    threads.clamp(1, (len / MIN_CHUNK).max(1))
+}
+
+/// Делит буфер на `parts` кусков примерно равного размера по границам строк
+///
+/// Каждый кусок, кроме последнего, заканчивается переводом строки.
+pub(super) fn split_lines(data: &[u8], parts: usize) -> Vec<&[u8]> {
+    let target = data.len().div_ceil(parts.max(1)).max(1);
+    let mut chunks = Vec::with_capacity(parts);
+    let mut rest = data;
+    while !rest.is_empty() {
+        let end = match rest.iter().skip(target).position(|&b| b == b'\n') {
+            Some(position) => target + position + 1,
+            None => rest.len(),
+        };
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
+
+/// Декодирует куски в отдельных потоках и сверяет итог в порядке файла
+///
+/// `decode` складывает элементы куска в вектор и останавливается на первой
+/// ошибке. Элементы до ошибки учитываются в итоге, поэтому возвращается та
+/// же ошибка, что и при последовательном чтении.
+pub(super) fn decode_chunks<F>(
+    chunks: &[&[u8]],
+    trailer: &mut TrailerCheck,
+    decode: F,
+) -> ParseResult<Vec<Transaction>>
+where
+    F: Fn(&[u8], &mut Vec<Entry>) -> ParseResult<()> + Sync,
+{
+    let decode_chunk = |chunk: &[u8]| {
+        let mut entries = Vec::new();
+        let error = decode(chunk, &mut entries).err();
+        (entries, error)
+    };
+
+    let decoded: Vec<(Vec<Entry>, Option<ParseError>)> = match chunks {
+        [chunk] => vec![decode_chunk(chunk)],
+        _ => thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .iter()
+                .map(|chunk| scope.spawn(|| decode_chunk(chunk)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                })
+                .collect()
+        }),
+    };
+
+    let mut transactions = Vec::with_capacity(decoded.iter().map(|(e, _)| e.len()).sum());
+    for (entries, error) in decoded {
+        for entry in entries {
+            match entry {
+                Entry::Transaction(transaction) => {
+                    trailer.record(transaction.tx_id, transaction.amount)?;
+                    transactions.push(transaction);
+                }
+                Entry::Trailer(totals) => trailer.trailer(totals)?,
+            }
+        }
+        if let Some(error) = error {
+            return Err(error);
+        }
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::Format;
+    use crate::{BinaryFormat, CsvFormat, FileHeader, TrailerPolicy};
+
+    fn sample(count: u64) -> ParseResult<Vec<Transaction>> {
+        (0..count)
+            .map(|i| {
+                Transaction::transfer(i % 10 + 1, i % 7 + 1, i as i64 + 1)
+                    .tx_id(i + 1)
+                    .timestamp(1000 + i)
+                    .description(format!("Перевод, \"№{i}\""))
+                    .build()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() -> ParseResult<()> {
+        let transactions = sample(3000)?;
+
+        let binary = BinaryFormat::new()
+            .with_header(FileHeader::new("test"))
+            .with_trailer(true);
+        let mut buffer = Vec::new();
+        binary.write_to(&mut buffer, &transactions)?;
+        assert!(chunk_count(buffer.len(), 8) > 1);
+        assert_eq!(binary.read_parallel(&buffer, 8)?, transactions);
+
+        let csv = CsvFormat::new().with_trailer(true);
+        let mut buffer = Vec::new();
+        csv.write_to(&mut buffer, &transactions)?;
+        assert!(chunk_count(buffer.len(), 8) > 1);
+        assert_eq!(csv.read_parallel(&buffer, 8)?, transactions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_reports_first_error() -> ParseResult<()> {
+        let transactions = sample(3000)?;
+
+        let csv = CsvFormat::new();
+        let mut buffer = Vec::new();
+        csv.write_to(&mut buffer, &transactions)?;
+        let text = String::from_utf8(buffer)?
+            .replace("\n10,", "\nX,")
+            .replace("\n2900,", "\n2900,TRANSFER,");
+        let sequential = csv.read_from(text.as_bytes()).unwrap_err();
+        let parallel = csv.read_parallel(text.as_bytes(), 8).unwrap_err();
+        assert_eq!(parallel.to_string(), sequential.to_string());
+
+        let binary = BinaryFormat::new().with_trailer_policy(TrailerPolicy::Require);
+        let mut buffer = Vec::new();
+        binary.write_to(&mut buffer, &transactions)?;
+        let result = binary.read_parallel(&buffer, 8);
+        assert!(matches!(result, Err(ParseError::MissingTrailer)));
+
+        buffer.truncate(buffer.len() - 1);
+        let result = binary.read_parallel(&buffer, 8);
+        assert!(matches!(result, Err(ParseError::Io(_))));
+        Ok(())
+    }
+}
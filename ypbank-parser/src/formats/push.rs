@@ -0,0 +1,308 @@
+use crate::{ParseError, ParseResult, Transaction};
+use std::io::{self, ErrorKind, Read};
+
+/// Сколько байт читать из источника за раз, как у `BufReader`
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Инкрементальный разбор без ввода-вывода
+///
+/// Парсер получает байты порциями произвольного размера и возвращает
+/// транзакции, которые в них завершились; незавершённая запись хранится
+/// внутри до следующей порции. Синхронные и асинхронные читатели построены
+/// поверх него, поэтому разбор и проверки итога у всех одни и те же.
+///
+/// После ошибки состояние парсера не определено: разбор нужно прекратить.
+pub trait PushParser {
+    /// Разбирает очередную порцию байтов и добавляет завершённые транзакции
+    /// в `out`. Транзакции, завершённые до ошибки, остаются в `out`.
+    fn feed_into(&mut self, data: &[u8], out: &mut Vec<Transaction>) -> ParseResult<()>;
+
+    /// Сообщает о конце данных: разбирает остаток и проверяет итог
+    fn finish_into(&mut self, out: &mut Vec<Transaction>) -> ParseResult<()>;
+
+    /// Разбирает очередную порцию байтов и возвращает завершённые транзакции
+    fn feed(&mut self, data: &[u8]) -> ParseResult<Vec<Transaction>> {
+        let mut out = Vec::new();
+        self.feed_into(data, &mut out)?;
+        Ok(out)
+    }
+
+    /// Сообщает о конце данных и возвращает последние транзакции
+    fn finish(&mut self) -> ParseResult<Vec<Transaction>> {
+        let mut out = Vec::new();
+        self.finish_into(&mut out)?;
+        Ok(out)
+    }
+}
+
+/// Строка буфера как текст, с той же ошибкой, что и у `BufRead::lines`
+pub(super) fn line_str(line: &[u8]) -> ParseResult<&str> {
+    std::str::from_utf8(line).map_err(|e| io::Error::new(ErrorKind::InvalidData, e).into())
+}
+
+/// Незавершённая строка построчных форматов между порциями
+#[derive(Debug, Default)]
+pub(super) struct LineBuffer {
+    partial: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Передаёт `handle` каждую строку, завершённую в `data`, без перевода
+    /// строки; остаток копится до следующего вызова
+    pub(super) fn feed<F>(&mut self, data: &[u8], mut handle: F) -> ParseResult<()>
+    where
+        F: FnMut(&str) -> ParseResult<()>,
+    {
+        let mut rest = data;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            let line = &rest[..end];
+            rest = &rest[end + 1..];
+            if self.partial.is_empty() {
+                handle(line_str(strip_cr(line))?)?;
+            } else {
+                self.partial.extend_from_slice(line);
+                let result = line_str(strip_cr(&self.partial)).and_then(&mut handle);
+                self.partial.clear();
+                result?;
+            }
+        }
+        self.partial.extend_from_slice(rest);
+        Ok(())
+    }
+
+    /// Передаёт `handle` последнюю строку без перевода строки, если она есть
+    pub(super) fn finish<F>(&mut self, handle: F) -> ParseResult<()>
+    where
+        F: FnOnce(&str) -> ParseResult<()>,
+    {
+        if self.partial.is_empty() {
+            return Ok(());
+        }
+        let partial = std::mem::take(&mut self.partial);
+        handle(line_str(&partial)?)
+    }
+}
+
+/// Отбрасывает `\r` перед переводом строки
+fn strip_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Разобранные транзакции между читателем источника и парсером
+///
+/// Транзакции отдаются по одной, а ошибка — после транзакций, завершённых
+/// до неё, как при чтении по одной записи.
+pub(super) struct Queue {
+    ready: std::vec::IntoIter<Transaction>,
+    error: Option<ParseError>,
+    /// Источник закончился или случилась ошибка: читать больше нечего
+    end: bool,
+}
+
+impl Queue {
+    pub(super) fn new() -> Self {
+        Queue {
+            ready: Vec::new().into_iter(),
+            error: None,
+            end: false,
+        }
+    }
+
+    /// Следующая готовая транзакция или ошибка
+    pub(super) fn pop(&mut self) -> Option<ParseResult<Transaction>> {
+        match self.ready.next() {
+            Some(transaction) => Some(Ok(transaction)),
+            None => self.error.take().map(Err),
+        }
+    }
+
+    /// Читать источник больше не нужно
+    pub(super) fn is_end(&self) -> bool {
+        self.end
+    }
+
+    /// Передаёт парсеру прочитанную порцию; пустая порция — конец источника
+    pub(super) fn push<P: PushParser>(&mut self, parser: &mut P, chunk: &[u8]) {
+        let mut out = Vec::new();
+        let result = if chunk.is_empty() {
+            self.end = true;
+            parser.finish_into(&mut out)
+        } else {
+            parser.feed_into(chunk, &mut out)
+        };
+        self.ready = out.into_iter();
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+
+    /// Запоминает ошибку чтения или разбора
+    pub(super) fn fail(&mut self, error: ParseError) {
+        self.error = Some(error);
+        self.end = true;
+    }
+
+    /// Забирает ошибку, если она случилась раньше готовых транзакций
+    pub(super) fn take_error(&mut self) -> Option<ParseError> {
+        if self.ready.len() > 0 {
+            return None;
+        }
+        self.error.take()
+    }
+}
+
+/// Потоковый читатель поверх [`PushParser`]
+///
+/// Читает источник порциями и передаёт их парсеру. Для каждого формата
+/// есть псевдоним: [`super::BinaryRecords`], [`super::CsvRecords`],
+/// [`super::TextRecords`].
+pub struct PushRecords<R, P> {
+    reader: R,
+    pub(super) parser: P,
+    buffer: Box<[u8]>,
+    queue: Queue,
+}
+
+impl<R: Read, P: PushParser> PushRecords<R, P> {
+    pub(super) fn new(reader: R, parser: P) -> Self {
+        PushRecords {
+            reader,
+            parser,
+            buffer: vec![0; READ_CHUNK].into_boxed_slice(),
+            queue: Queue::new(),
+        }
+    }
+
+    /// Читает и разбирает очередную порцию; `false` — читать больше нечего
+    pub(super) fn fill(&mut self) -> bool {
+        if self.queue.is_end() {
+            return false;
+        }
+        match read_chunk(&mut self.reader, &mut self.buffer) {
+            Ok(count) => self.queue.push(&mut self.parser, &self.buffer[..count]),
+            Err(e) => self.queue.fail(e.into()),
+        }
+        true
+    }
+
+    /// Забирает ошибку, если она случилась раньше готовых транзакций
+    pub(super) fn take_error(&mut self) -> Option<ParseError> {
+        self.queue.take_error()
+    }
+}
+
+/// Читает из источника, повторяя прерванные вызовы
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match reader.read(buffer) {
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+impl<R: Read, P: PushParser> Iterator for PushRecords<R, P> {
+    type Item = ParseResult<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.queue.pop() {
+                return Some(result);
+            }
+            if !self.fill() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::Format;
+    use crate::{BinaryFormat, CsvFormat, FileHeader, TextFormat, TrailerPolicy};
+
+    fn sample() -> ParseResult<Vec<Transaction>> {
+        (1..=50)
+            .map(|i| {
+                Transaction::transfer(i % 10 + 1, i % 7 + 1, i as i64)
+                    .tx_id(i)
+                    .timestamp(1000 + i)
+                    .description(format!("Перевод \"№{i}\""))
+                    .metadata("channel", "app")
+                    .build()
+            })
+            .collect()
+    }
+
+    /// Подаёт данные парсеру порциями по `step` байт
+    fn feed_by<P: PushParser>(
+        mut parser: P,
+        data: &[u8],
+        step: usize,
+    ) -> ParseResult<Vec<Transaction>> {
+        let mut out = Vec::new();
+        for chunk in data.chunks(step) {
+            parser.feed_into(chunk, &mut out)?;
+        }
+        parser.finish_into(&mut out)?;
+        Ok(out)
+    }
+
+    fn check_push<F: Format>(format: &F) -> ParseResult<()> {
+        let transactions = sample()?;
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &transactions)?;
+        for step in [1, 7, 4096] {
+            assert_eq!(feed_by(format.push_parser(), &buffer, step)?, transactions);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_parsers_any_chunking() -> ParseResult<()> {
+        check_push(
+            &BinaryFormat::new()
+                .with_header(FileHeader::new("test"))
+                .with_trailer(true),
+        )?;
+        check_push(&CsvFormat::new().with_trailer(true))?;
+        check_push(&TextFormat::new().with_trailer(true))?;
+
+        let crlf = b"TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\r\n\
+            1,DEPOSIT,0,1,100,1000,SUCCESS,\"a\"\r\n";
+        let parsed = feed_by(CsvFormat::new().push_parser(), crlf, 3)?;
+        assert_eq!(parsed.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_parser_keeps_records_before_error() -> ParseResult<()> {
+        let transactions = sample()?;
+        let format = BinaryFormat::new().with_trailer_policy(TrailerPolicy::Require);
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &transactions)?;
+
+        let mut parser = format.push_parser();
+        let mut out = Vec::new();
+        parser.feed_into(&buffer, &mut out)?;
+        assert_eq!(out, transactions);
+        assert!(matches!(parser.finish(), Err(ParseError::MissingTrailer)));
+
+        // Обрыв посреди записи обнаруживается только в конце данных
+        let mut parser = format.push_parser();
+        let read = parser.feed(&buffer[..buffer.len() - 1])?;
+        assert_eq!(read.len(), transactions.len() - 1);
+        assert!(matches!(parser.finish(), Err(ParseError::Io(_))));
+
+        let mut text = Vec::new();
+        TextFormat::new().write_to(&mut text, &transactions[..3])?;
+        let broken = String::from_utf8(text)?.replace("AMOUNT: 2", "AMOUNT: x");
+        let mut parser = TextFormat::new().push_parser();
+        let mut out = Vec::new();
+        let result = parser.feed_into(broken.as_bytes(), &mut out);
+        assert!(result.is_err());
+        assert_eq!(out, transactions[..1]);
+        Ok(())
+    }
+}
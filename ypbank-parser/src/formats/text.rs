@@ -1,14 +1,87 @@
-use super::Format;
-use crate::{ParseError, ParseResult, Transaction, TransactionStatus, TransactionType};
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read, Write};
+use super::push::{LineBuffer, PushParser, PushRecords};
+use super::trailer::{Totals, TrailerCheck, TrailerPolicy};
+use super::{Format, METADATA_PREFIX, check_description, check_metadata};
+use crate::{
+    ParseError, ParseResult, Transaction, TransactionStatus, TransactionType, UnknownCodePolicy,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+/// Начало строки с итогом: `# TOTAL: COUNT=<число>, AMOUNT=<сумма>`
+///
+/// Для прежних читателей это обычный комментарий.
+pub(super) const TRAILER_PREFIX: &str = "# TOTAL:";
 
 /// Парсер для текстового формата YPBankText
-pub struct TextFormat;
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TextFormat {
+    unknown_codes: UnknownCodePolicy,
+    pub(super) trailer: bool,
+    trailer_policy: TrailerPolicy,
+}
 
 impl TextFormat {
+    /// Создаёт парсер с настройками по умолчанию
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Задаёт политику для неизвестных кодов типа и статуса
+    pub fn with_unknown_codes(mut self, policy: UnknownCodePolicy) -> Self {
+        self.unknown_codes = policy;
+        self
+    }
+
+    /// Дописывать ли в конец строку с числом записей и суммой
+    pub fn with_trailer(mut self, trailer: bool) -> Self {
+        self.trailer = trailer;
+        self
+    }
+
+    /// Задаёт, обязательна ли строка итога при чтении
+    pub fn with_trailer_policy(mut self, policy: TrailerPolicy) -> Self {
+        self.trailer_policy = policy;
+        self
+    }
+
+    /// Проверяет, что транзакцию можно записать в текстовом формате
+    pub(super) fn check_transaction(transaction: &Transaction) -> ParseResult<()> {
+        check_description(transaction)?;
+        check_metadata(transaction)
+    }
+
+    /// Записывает одну транзакцию блоком строк `КЛЮЧ: значение`
+    pub(super) fn write_transaction<W: Write>(
+        writer: &mut W,
+        transaction: &Transaction,
+    ) -> ParseResult<()> {
+        Self::check_transaction(transaction)?;
+        writeln!(writer, "TX_ID: {}", transaction.tx_id)?;
+        writeln!(writer, "TX_TYPE: {}", transaction.tx_type)?;
+        writeln!(writer, "FROM_USER_ID: {}", transaction.from_user_id)?;
+        writeln!(writer, "TO_USER_ID: {}", transaction.to_user_id)?;
+        writeln!(writer, "AMOUNT: {}", transaction.amount)?;
+        writeln!(writer, "TIMESTAMP: {}", transaction.timestamp)?;
+        writeln!(writer, "STATUS: {}", transaction.status)?;
+        writeln!(
+            writer,
+            "DESCRIPTION: \"{}\"",
+            transaction
+                .description
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+        )?;
+        if let Some(parent_tx_id) = transaction.parent_tx_id {
+            writeln!(writer, "PARENT_TX_ID: {}", parent_tx_id)?;
+        }
+        for (key, value) in &transaction.metadata {
+            writeln!(writer, "{METADATA_PREFIX}{key}: {value}")?;
+        }
+        Ok(())
+    }
+
     /// Парсит блок текста в транзакцию
-    fn parse_text_block(block: &str) -> ParseResult<Transaction> {
+    fn parse_text_block(&self, block: &str) -> ParseResult<Transaction> {
         let mut fields = HashMap::new();
 
         for line in block.lines() {
@@ -33,13 +106,29 @@ impl TextFormat {
         };
 
         let tx_id = get_field("TX_ID")?.parse()?;
-        let tx_type = TransactionType::try_from(*get_field("TX_TYPE")?)?;
+        let tx_type = TransactionType::from_name(get_field("TX_TYPE")?, self.unknown_codes)?;
         let from_user_id = get_field("FROM_USER_ID")?.parse()?;
         let to_user_id = get_field("TO_USER_ID")?.parse()?;
         let amount = get_field("AMOUNT")?.parse()?;
         let timestamp = get_field("TIMESTAMP")?.parse()?;
-        let status = TransactionStatus::try_from(*get_field("STATUS")?)?;
-        let description = get_field("DESCRIPTION")?.trim_matches('"').to_string();
+        let status = TransactionStatus::from_name(get_field("STATUS")?, self.unknown_codes)?;
+        let description = unquote(get_field("DESCRIPTION")?);
+        let parent_tx_id = fields
+            .get("PARENT_TX_ID")
+            .map(|value| value.parse())
+            .transpose()?;
+
+        let mut metadata = BTreeMap::new();
+        for (key, value) in &fields {
+            if let Some(meta_key) = key.strip_prefix(METADATA_PREFIX) {
+                if meta_key.is_empty() {
+                    return Err(ParseError::InvalidFormat(format!(
+                        "Пустой ключ в поле {key}"
+                    )));
+                }
+                metadata.insert(meta_key.to_string(), value.to_string());
+            }
+        }
 
         Ok(Transaction {
             tx_id,
@@ -50,54 +139,226 @@ impl TextFormat {
             timestamp,
             status,
             description,
+            parent_tx_id,
+            metadata,
         })
     }
 }
 
-impl Format for TextFormat {
-    fn read_from<R: Read>(&self, reader: R) -> ParseResult<Vec<Transaction>> {
-        let mut transactions = Vec::new();
-        let buf_reader = BufReader::new(reader);
-        let mut current_block = String::new();
-
-        for line in buf_reader.lines() {
-            let line = line?;
-            let trimmed = line.trim();
-
-            if trimmed.is_empty() {
-                if !current_block.is_empty() {
-                    let transaction = Self::parse_text_block(&current_block)?;
-                    transactions.push(transaction);
-                    current_block.clear();
-                }
-            } else {
-                current_block.push_str(&line);
-                current_block.push('\n');
+/// Записывает строку итога после пустой строки
+pub(super) fn write_trailer<W: Write>(writer: &mut W, totals: Totals) -> ParseResult<()> {
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "{TRAILER_PREFIX} COUNT={}, AMOUNT={}",
+        totals.count, totals.amount
+    )?;
+    Ok(())
+}
+
+/// Разбирает строку итога
+fn parse_trailer(line: &str) -> ParseResult<Totals> {
+    let invalid = || ParseError::InvalidFormat(format!("Неверная строка итога: {line}"));
+
+    let rest = line.strip_prefix(TRAILER_PREFIX).ok_or_else(invalid)?;
+    let (count, amount) = rest.trim().split_once(',').ok_or_else(invalid)?;
+    let count = count.trim().strip_prefix("COUNT=").ok_or_else(invalid)?;
+    let amount = amount.trim().strip_prefix("AMOUNT=").ok_or_else(invalid)?;
+    Ok(Totals {
+        count: count.parse()?,
+        amount: amount.parse()?,
+    })
+}
+
+/// Снимает кавычки с описания и раскрывает `\"` и `\\`
+///
+/// Значение без кавычек возвращается как есть, обратная косая черта перед
+/// другими символами сохраняется.
+fn unquote(value: &str) -> String {
+    let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    else {
+        return value.to_string();
+    };
+
+    let mut description = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            description.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(escaped @ ('\\' | '"')) => description.push(escaped),
+            Some(other) => {
+                description.push('\\');
+                description.push(other);
             }
+            None => description.push('\\'),
+        }
+    }
+    description
+}
+
+/// Построчный разбор YPBankText
+///
+/// Основа [`TextPushParser`]: накапливает строки блока до пустой строки
+/// и сверяет итог.
+pub(super) struct LineDecoder {
+    format: TextFormat,
+    current_block: String,
+    /// Итог, прочитанный сразу после незавершённого блока
+    pending_trailer: Option<Totals>,
+    trailer: TrailerCheck,
+}
+
+impl LineDecoder {
+    pub(super) fn new(format: TextFormat) -> Self {
+        LineDecoder {
+            format,
+            current_block: String::new(),
+            pending_trailer: None,
+            trailer: TrailerCheck::new(format.trailer_policy),
+        }
+    }
+
+    /// Разбирает строку без перевода строки; транзакция возвращается,
+    /// когда строка завершает блок
+    pub(super) fn decode(&mut self, line: &str) -> ParseResult<Option<Transaction>> {
+        if let Some(totals) = self.pending_trailer.take() {
+            self.trailer.trailer(totals)?;
         }
 
-        if !current_block.is_empty() {
-            let transaction = Self::parse_text_block(&current_block)?;
-            transactions.push(transaction);
+        let trimmed = line.trim();
+        if trimmed.starts_with(TRAILER_PREFIX) {
+            let totals = parse_trailer(trimmed)?;
+            if self.current_block.is_empty() {
+                self.trailer.trailer(totals)?;
+                return Ok(None);
+            }
+            self.pending_trailer = Some(totals);
+            return self.finish_block().map(Some);
+        }
+        if trimmed.is_empty() {
+            if !self.current_block.is_empty() {
+                return self.finish_block().map(Some);
+            }
+        } else {
+            self.current_block.push_str(line);
+            self.current_block.push('\n');
+        }
+        Ok(None)
+    }
+
+    /// Завершает источник: возвращает последний блок, пока он есть, затем
+    /// проверяет итог
+    pub(super) fn finish(&mut self) -> ParseResult<Option<Transaction>> {
+        if let Some(totals) = self.pending_trailer.take() {
+            self.trailer.trailer(totals)?;
+        }
+        if !self.current_block.is_empty() {
+            return self.finish_block().map(Some);
         }
 
-        Ok(transactions)
+        self.trailer.finish()?;
+        Ok(None)
+    }
+
+    /// Разбирает накопленный блок и учитывает транзакцию в итоге
+    fn finish_block(&mut self) -> ParseResult<Transaction> {
+        let transaction = self.format.parse_text_block(&self.current_block)?;
+        self.current_block.clear();
+        self.trailer.record(transaction.tx_id, transaction.amount)?;
+        Ok(transaction)
+    }
+}
+
+/// Инкрементальный разбор YPBankText
+///
+/// Копит незавершённую строку между порциями; последний блок без пустой
+/// строки после него возвращается при завершении. Создаётся
+/// [`Format::push_parser`].
+pub struct TextPushParser {
+    lines: LineBuffer,
+    decoder: LineDecoder,
+}
+
+impl PushParser for TextPushParser {
+    fn feed_into(&mut self, data: &[u8], out: &mut Vec<Transaction>) -> ParseResult<()> {
+        let decoder = &mut self.decoder;
+        self.lines.feed(data, |line| {
+            out.extend(decoder.decode(line)?);
+            Ok(())
+        })
+    }
+
+    fn finish_into(&mut self, out: &mut Vec<Transaction>) -> ParseResult<()> {
+        let decoder = &mut self.decoder;
+        self.lines.finish(|line| {
+            out.extend(decoder.decode(line)?);
+            Ok(())
+        })?;
+        while let Some(transaction) = self.decoder.finish()? {
+            out.push(transaction);
+        }
+        Ok(())
+    }
+}
+
+/// Потоковый читатель формата YPBankText
+pub type TextRecords<R> = PushRecords<R, TextPushParser>;
+
+impl Format for TextFormat {
+    type Records<R: Read> = TextRecords<R>;
+    type Parser = TextPushParser;
+
+    fn records<R: Read>(&self, reader: R) -> Self::Records<R> {
+        PushRecords::new(reader, self.push_parser())
+    }
+
+    fn push_parser(&self) -> Self::Parser {
+        TextPushParser {
+            lines: LineBuffer::default(),
+            decoder: LineDecoder::new(*self),
+        }
     }
 
     fn write_to<W: Write>(&self, mut writer: W, transactions: &[Transaction]) -> ParseResult<()> {
+        for transaction in transactions {
+            Self::check_transaction(transaction)?;
+        }
+        let mut totals = Totals::default();
         for (i, transaction) in transactions.iter().enumerate() {
             if i > 0 {
                 writeln!(writer)?;
             }
+            Self::write_transaction(&mut writer, transaction)?;
+            totals.add(transaction);
+        }
+        if self.trailer {
+            write_trailer(&mut writer, totals)?;
+        }
+
+        Ok(())
+    }
 
-            writeln!(writer, "TX_ID: {}", transaction.tx_id)?;
-            writeln!(writer, "TX_TYPE: {}", transaction.tx_type)?;
-            writeln!(writer, "FROM_USER_ID: {}", transaction.from_user_id)?;
-            writeln!(writer, "TO_USER_ID: {}", transaction.to_user_id)?;
-            writeln!(writer, "AMOUNT: {}", transaction.amount)?;
-            writeln!(writer, "TIMESTAMP: {}", transaction.timestamp)?;
-            writeln!(writer, "STATUS: {}", transaction.status)?;
-            writeln!(writer, "DESCRIPTION: {}", transaction.description)?;
+    fn write_records<W, I>(&self, mut writer: W, records: I) -> ParseResult<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = ParseResult<Transaction>>,
+    {
+        let mut totals = Totals::default();
+        for (i, transaction) in records.into_iter().enumerate() {
+            let transaction = transaction?;
+            if i > 0 {
+                writeln!(writer)?;
+            }
+            Self::write_transaction(&mut writer, &transaction)?;
+            totals.add(&transaction);
+        }
+        if self.trailer {
+            write_trailer(&mut writer, totals)?;
         }
 
         Ok(())
@@ -122,8 +383,10 @@ mod tests {
             timestamp: 1633036800000,
             status: TransactionStatus::Success,
             description: "Terminal deposit".to_string(),
+            parent_tx_id: None,
+            metadata: BTreeMap::new(),
         }];
-        let format = TextFormat;
+        let format = TextFormat::new();
         let mut buffer = Vec::new();
 
         format.write_to(&mut buffer, &transaction)?;
@@ -158,7 +421,7 @@ TIMESTAMP: 2000
 STATUS: PENDING
 DESCRIPTION: \"\"
 ";
-        let format = TextFormat;
+        let format = TextFormat::new();
         let txs = format.read_from(Cursor::new(data)).unwrap();
         assert_eq!(txs.len(), 2);
         assert_eq!(txs[0].tx_id, 1);
@@ -176,7 +439,7 @@ AMOUNT: 1000
 TIMESTAMP: 1000
 STATUS: SUCCESS
 ";
-        let format = TextFormat;
+        let format = TextFormat::new();
         let result = format.read_from(Cursor::new(data));
         assert!(matches!(result, Err(ParseError::MissingField(field)) if field == "DESCRIPTION"));
     }
@@ -193,7 +456,7 @@ TIMESTAMP: 1000
 STATUS: SUCCESS
 DESCRIPTION: \"test\"
 ";
-        let format = TextFormat;
+        let format = TextFormat::new();
         let txs = format.read_from(Cursor::new(data)).unwrap();
         assert_eq!(txs.len(), 1);
     }
@@ -210,8 +473,114 @@ TIMESTAMP: 1000
 STATUS: SUCCESS
 DESCRIPTION: \"test\"
 ";
-        let format = TextFormat;
+        let format = TextFormat::new();
         let result = format.read_from(Cursor::new(data));
         assert!(matches!(result, Err(ParseError::InvalidTransactionType(_))));
     }
+
+    #[test]
+    fn test_text_parent_tx_id() {
+        let data = "\
+TX_ID: 2
+TX_TYPE: REFUND
+FROM_USER_ID: 100
+TO_USER_ID: 200
+AMOUNT: 1000
+TIMESTAMP: 2000
+STATUS: SUCCESS
+DESCRIPTION: \"refund\"
+PARENT_TX_ID: 1
+";
+        let format = TextFormat::new();
+        let txs = format.read_from(Cursor::new(data)).unwrap();
+        assert_eq!(txs[0].tx_type, TransactionType::Refund);
+        assert_eq!(txs[0].parent_tx_id, Some(1));
+
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &txs).unwrap();
+        assert_eq!(format.read_from(Cursor::new(buffer)).unwrap(), txs);
+    }
+
+    #[test]
+    fn test_text_metadata() {
+        let data = "\
+TX_ID: 1
+TX_TYPE: DEPOSIT
+FROM_USER_ID: 0
+TO_USER_ID: 100
+AMOUNT: 1000
+TIMESTAMP: 1000
+STATUS: SUCCESS
+DESCRIPTION: \"atm\"
+META_terminal_id: T-17
+META_channel: atm, lobby
+";
+        let format = TextFormat::new();
+        let txs = format.read_from(Cursor::new(data)).unwrap();
+        assert_eq!(txs[0].metadata["terminal_id"], "T-17");
+        assert_eq!(txs[0].metadata["channel"], "atm, lobby");
+
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &txs).unwrap();
+        let written = String::from_utf8(buffer.clone()).unwrap();
+        assert!(written.ends_with("META_channel: atm, lobby\nMETA_terminal_id: T-17\n"));
+        assert_eq!(format.read_from(Cursor::new(buffer)).unwrap(), txs);
+    }
+
+    #[test]
+    fn test_text_description_escaping() -> ParseResult<()> {
+        let txs = vec![
+            Transaction::deposit(501, 100)
+                .description("\"quoted\"")
+                .build()?,
+            Transaction::deposit(501, 200)
+                .description(" C:\\temp\\ ")
+                .build()?,
+        ];
+        let format = TextFormat::new();
+        let mut buffer = Vec::new();
+        format.write_to(&mut buffer, &txs)?;
+
+        let written = String::from_utf8(buffer.clone()).unwrap();
+        assert!(written.contains("DESCRIPTION: \"\\\"quoted\\\"\"\n"));
+        assert_eq!(format.read_from(Cursor::new(buffer))?, txs);
+        assert_eq!(unquote("plain"), "plain");
+        assert_eq!(unquote("\"a\\nb\""), "a\\nb");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_trailer() -> ParseResult<()> {
+        let txs = vec![
+            Transaction::deposit(501, 100).tx_id(1).build()?,
+            Transaction::withdrawal(501, 40).tx_id(2).build()?,
+        ];
+        let mut buffer = Vec::new();
+        TextFormat::new()
+            .with_trailer(true)
+            .write_to(&mut buffer, &txs)?;
+        let written = String::from_utf8(buffer).unwrap();
+        assert!(written.ends_with("\n\n# TOTAL: COUNT=2, AMOUNT=140\n"));
+
+        let required = TextFormat::new().with_trailer_policy(TrailerPolicy::Require);
+        assert_eq!(required.read_from(Cursor::new(&written))?, txs);
+
+        // Итог сразу после блока без пустой строки
+        let compact = written.replace("\n\n# TOTAL", "\n# TOTAL");
+        assert_eq!(required.read_from(Cursor::new(compact))?, txs);
+
+        let first_block = written.split("\n\n").next().unwrap();
+        assert!(matches!(
+            required.read_from(Cursor::new(first_block)),
+            Err(ParseError::MissingTrailer)
+        ));
+        let missing_record = format!("{first_block}\n\n# TOTAL: COUNT=2, AMOUNT=140\n");
+        assert!(matches!(
+            TextFormat::new().read_from(Cursor::new(missing_record)),
+            Err(ParseError::TrailerMismatch { .. })
+        ));
+
+        Ok(())
+    }
 }
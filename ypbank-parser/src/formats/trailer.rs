@@ -0,0 +1,88 @@
+use crate::{ParseError, ParseResult, Transaction};
+
+/// Контрольный итог выгрузки: число записей и сумма `AMOUNT`
+///
+/// Сумма хранится в `i128`, чтобы не переполниться на больших выгрузках.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Totals {
+    pub count: u64,
+    pub amount: i128,
+}
+
+impl Totals {
+    /// Учитывает транзакцию в итоге
+    pub fn add(&mut self, transaction: &Transaction) {
+        self.count += 1;
+        self.amount += i128::from(transaction.amount);
+    }
+}
+
+/// Как читатели поступают с итоговой записью
+///
+/// Итог, если он есть, сверяется всегда; `Require` дополнительно считает
+/// ошибкой источник без итога, например обрезанный по границе строки.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum TrailerPolicy {
+    #[default]
+    Optional,
+    Require,
+}
+
+/// Сверка итога по мере чтения записей
+#[derive(Debug, Default)]
+pub(super) struct TrailerCheck {
+    policy: TrailerPolicy,
+    totals: Totals,
+    seen: bool,
+}
+
+impl TrailerCheck {
+    pub(super) fn new(policy: TrailerPolicy) -> Self {
+        TrailerCheck {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// Делает итог обязательным, например по заголовку файла
+    pub(super) fn require(&mut self) {
+        self.policy = TrailerPolicy::Require;
+    }
+
+    /// Учитывает прочитанную транзакцию; после итога записей быть не должно
+    pub(super) fn record(&mut self, tx_id: u64, amount: i64) -> ParseResult<()> {
+        if self.seen {
+            return Err(ParseError::InvalidFormat(format!(
+                "Транзакция {tx_id} после итоговой записи"
+            )));
+        }
+        self.totals.count += 1;
+        self.totals.amount += i128::from(amount);
+        Ok(())
+    }
+
+    /// Сверяет итоговую запись с прочитанными транзакциями
+    pub(super) fn trailer(&mut self, expected: Totals) -> ParseResult<()> {
+        if self.seen {
+            return Err(ParseError::InvalidFormat(
+                "Повторная итоговая запись".to_string(),
+            ));
+        }
+        self.seen = true;
+        if expected != self.totals {
+            return Err(ParseError::TrailerMismatch {
+                expected,
+                actual: self.totals,
+            });
+        }
+        Ok(())
+    }
+
+    /// Проверяет, что итог был, если он обязателен
+    pub(super) fn finish(&self) -> ParseResult<()> {
+        if self.policy == TrailerPolicy::Require && !self.seen {
+            return Err(ParseError::MissingTrailer);
+        }
+        Ok(())
+    }
+}
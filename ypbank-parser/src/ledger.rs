@@ -0,0 +1,296 @@
+use crate::{ParseError, ParseResult, Transaction, TransactionStatus, TransactionType};
+use std::collections::BTreeMap;
+
+/// Баланс пользователя
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Balance {
+    /// Сумма по исполненным операциям
+    pub settled: i64,
+    /// Списания в статусе `PENDING`, зарезервированные на счёте
+    pub held_out: i64,
+    /// Поступления в статусе `PENDING`, ещё не зачисленные
+    pub held_in: i64,
+}
+
+impl Balance {
+    /// Доступный остаток: исполненные операции за вычетом резерва.
+    /// `None` при переполнении.
+    pub fn available(&self) -> Option<i64> {
+        self.settled.checked_sub(self.held_out)
+    }
+}
+
+/// Операция, после которой доступный остаток пользователя стал отрицательным
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Overdraft {
+    pub tx_id: u64,
+    pub user_id: u64,
+    pub available: i64,
+}
+
+/// Книга балансов, которая накапливает транзакции по пользователям
+///
+/// Пополнение зачисляет сумму `to_user_id`, списание и комиссия списывают
+/// её с `from_user_id`, перевод, возврат и опротестование переносят сумму
+/// от `from_user_id` к `to_user_id`. Пользователь `0` — внешний контрагент,
+/// его баланс не ведётся. По умолчанию исполненными считаются только
+/// транзакции `SUCCESS`, транзакции `PENDING` учитываются как резерв,
+/// остальные статусы пропускаются. Транзакции с неизвестным типом или
+/// статусом, прочитанные с `UnknownCodePolicy::Preserve`, не учитываются
+/// и считаются в [`Ledger::skipped`].
+#[derive(Debug, Clone)]
+pub struct Ledger {
+    balances: BTreeMap<u64, Balance>,
+    settled_statuses: Vec<TransactionStatus>,
+    overdrafts: Vec<Overdraft>,
+    skipped: u64,
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Ledger {
+            balances: BTreeMap::new(),
+            settled_statuses: vec![TransactionStatus::Success],
+            overdrafts: Vec::new(),
+            skipped: 0,
+        }
+    }
+}
+
+impl Ledger {
+    /// Создаёт пустую книгу балансов
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Задаёт статусы, транзакции с которыми считаются исполненными
+    pub fn with_settled_statuses(mut self, statuses: &[TransactionStatus]) -> Self {
+        self.settled_statuses = statuses.to_vec();
+        self
+    }
+
+    /// Учитывает одну транзакцию
+    ///
+    /// При переполнении возвращается ошибка, и книга остаётся неизменной.
+    pub fn apply(&mut self, transaction: &Transaction) -> ParseResult<()> {
+        let (debit, credit) = match transaction.tx_type {
+            TransactionType::Deposit => (None, Some(transaction.to_user_id)),
+            TransactionType::Withdrawal | TransactionType::Fee => {
+                (Some(transaction.from_user_id), None)
+            }
+            TransactionType::Transfer | TransactionType::Refund | TransactionType::Chargeback => {
+                (Some(transaction.from_user_id), Some(transaction.to_user_id))
+            }
+            TransactionType::Unknown(_) => {
+                self.skipped += 1;
+                return Ok(());
+            }
+        };
+        if let TransactionStatus::Unknown(_) = transaction.status {
+            self.skipped += 1;
+            return Ok(());
+        }
+
+        let settled = self.settled_statuses.contains(&transaction.status);
+        let pending = !settled && transaction.status == TransactionStatus::Pending;
+        if !settled && !pending {
+            return Ok(());
+        }
+
+        let amount = transaction.amount;
+        let overflow = |user_id| ParseError::BalanceOverflow {
+            user_id,
+            tx_id: transaction.tx_id,
+        };
+
+        // Изменения собираются отдельно, чтобы при ошибке книга не менялась
+        let mut changed = BTreeMap::new();
+        if let Some(user_id) = debit.filter(|&id| id != 0) {
+            let balance: &mut Balance = changed
+                .entry(user_id)
+                .or_insert_with(|| self.balance(user_id));
+            if settled {
+                balance.settled = balance
+                    .settled
+                    .checked_sub(amount)
+                    .ok_or_else(|| overflow(user_id))?;
+            } else {
+                balance.held_out = balance
+                    .held_out
+                    .checked_add(amount)
+                    .ok_or_else(|| overflow(user_id))?;
+            }
+        }
+        if let Some(user_id) = credit.filter(|&id| id != 0) {
+            let balance: &mut Balance = changed
+                .entry(user_id)
+                .or_insert_with(|| self.balance(user_id));
+            if settled {
+                balance.settled = balance
+                    .settled
+                    .checked_add(amount)
+                    .ok_or_else(|| overflow(user_id))?;
+            } else {
+                balance.held_in = balance
+                    .held_in
+                    .checked_add(amount)
+                    .ok_or_else(|| overflow(user_id))?;
+            }
+        }
+
+        let mut overdraft = None;
+        for (&user_id, balance) in &changed {
+            let available = balance.available().ok_or_else(|| overflow(user_id))?;
+            if available < 0 && debit == Some(user_id) {
+                overdraft = Some(Overdraft {
+                    tx_id: transaction.tx_id,
+                    user_id,
+                    available,
+                });
+            }
+        }
+
+        self.overdrafts.extend(overdraft);
+        self.balances.extend(changed);
+
+        Ok(())
+    }
+
+    /// Учитывает транзакции по порядку
+    pub fn apply_all<'a, I>(&mut self, transactions: I) -> ParseResult<()>
+    where
+        I: IntoIterator<Item = &'a Transaction>,
+    {
+        for transaction in transactions {
+            self.apply(transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Баланс пользователя; нулевой, если операций не было
+    pub fn balance(&self, user_id: u64) -> Balance {
+        self.balances.get(&user_id).copied().unwrap_or_default()
+    }
+
+    /// Балансы всех пользователей в порядке возрастания идентификатора
+    pub fn balances(&self) -> &BTreeMap<u64, Balance> {
+        &self.balances
+    }
+
+    /// Операции, которые увели доступный остаток в минус, в порядке учёта
+    pub fn overdrafts(&self) -> &[Overdraft] {
+        &self.overdrafts
+    }
+
+    /// Число пропущенных транзакций с неизвестным типом или статусом
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_balances() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(1, 1_000).tx_id(1).build()?,
+            Transaction::transfer(1, 2, 300).tx_id(2).build()?,
+            Transaction::withdrawal(2, 100).tx_id(3).build()?,
+            Transaction::transfer(1, 2, 50)
+                .tx_id(4)
+                .status(TransactionStatus::Pending)
+                .build()?,
+            Transaction::withdrawal(1, 500)
+                .tx_id(5)
+                .status(TransactionStatus::Failure)
+                .build()?,
+        ];
+
+        let mut ledger = Ledger::new();
+        ledger.apply_all(&transactions)?;
+
+        let first = ledger.balance(1);
+        assert_eq!(first.settled, 700);
+        assert_eq!(first.held_out, 50);
+        assert_eq!(first.available(), Some(650));
+
+        let second = ledger.balance(2);
+        assert_eq!(second.settled, 200);
+        assert_eq!(second.held_in, 50);
+
+        assert!(!ledger.balances().contains_key(&0));
+        assert!(ledger.overdrafts().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ledger_overdraft() -> ParseResult<()> {
+        let mut ledger = Ledger::new();
+        ledger.apply(&Transaction::deposit(1, 100).tx_id(1).build()?)?;
+        ledger.apply(&Transaction::transfer(1, 2, 150).tx_id(2).build()?)?;
+
+        assert_eq!(
+            ledger.overdrafts(),
+            &[Overdraft {
+                tx_id: 2,
+                user_id: 1,
+                available: -50
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ledger_overflow() -> ParseResult<()> {
+        let mut ledger = Ledger::new();
+        ledger.apply(&Transaction::deposit(1, i64::MAX).tx_id(1).build()?)?;
+
+        let result = ledger.apply(&Transaction::deposit(1, 1).tx_id(2).build()?);
+        assert!(matches!(
+            result,
+            Err(ParseError::BalanceOverflow {
+                user_id: 1,
+                tx_id: 2
+            })
+        ));
+        assert_eq!(ledger.balance(1).settled, i64::MAX);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ledger_skips_unknown_codes() -> ParseResult<()> {
+        let mut ledger = Ledger::new();
+        ledger.apply(&Transaction::deposit(1, 100).tx_id(1).build()?)?;
+        let mut unknown_type = Transaction::deposit(1, 50).tx_id(2).build()?;
+        unknown_type.tx_type = TransactionType::Unknown(9);
+        let mut unknown_status = Transaction::withdrawal(1, 30).tx_id(3).build()?;
+        unknown_status.status = TransactionStatus::Unknown(7);
+        ledger.apply_all([&unknown_type, &unknown_status])?;
+
+        assert_eq!(ledger.balance(1).settled, 100);
+        assert_eq!(ledger.skipped(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ledger_pending_as_settled() -> ParseResult<()> {
+        let mut ledger = Ledger::new()
+            .with_settled_statuses(&[TransactionStatus::Success, TransactionStatus::Pending]);
+        ledger.apply(
+            &Transaction::deposit(1, 100)
+                .status(TransactionStatus::Pending)
+                .build()?,
+        )?;
+
+        assert_eq!(ledger.balance(1).settled, 100);
+        assert_eq!(ledger.balance(1).held_in, 0);
+
+        Ok(())
+    }
+}
@@ -1,7 +1,38 @@
+mod chain;
 mod error;
+mod filter;
 mod formats;
+mod ledger;
+mod query;
+mod sort;
+mod split;
+mod statement;
+mod stats;
 mod transaction;
 
+pub use chain::related_chain;
 pub use error::{ParseError, ParseResult};
-pub use formats::{BinaryFormat, CsvFormat, Format, TextFormat};
-pub use transaction::{Transaction, TransactionStatus, TransactionType};
+pub use filter::TransactionFilter;
+#[cfg(feature = "codec")]
+pub use formats::BinaryCodec;
+#[cfg(feature = "async")]
+pub use formats::{
+    AsyncBinaryRecords, AsyncCsvRecords, AsyncFormat, AsyncPushRecords, AsyncTextRecords,
+};
+pub use formats::{
+    BinaryFormat, BinaryIndex, BinaryPushParser, BinaryRecords, BorrowedRecords, CsvColumns,
+    CsvFormat, CsvPushParser, CsvRecords, FileHeader, Format, FormatKind, IndexedReader,
+    MappedFile, MetadataRefs, PushParser, PushRecords, TextFormat, TextPushParser, TextRecords,
+    TimestampRange, Totals, TrailerPolicy, TransactionRef,
+};
+pub use ledger::{Balance, Ledger, Overdraft};
+pub use query::Query;
+pub use sort::{
+    Deduplicated, DuplicatePolicy, ExternalSorter, MergedRecords, SortKey, SortOrder, SortedRun,
+};
+pub use split::PathTemplate;
+pub use statement::{Statement, StatementEntry, TypeTotal};
+pub use stats::Stats;
+pub use transaction::{
+    Transaction, TransactionBuilder, TransactionStatus, TransactionType, UnknownCodePolicy,
+};
@@ -0,0 +1,601 @@
+use crate::{
+    ParseError, ParseResult, Transaction, TransactionStatus, TransactionType, UnknownCodePolicy,
+};
+use regex::Regex;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// Выражение для отбора транзакций
+///
+/// Выражение состоит из сравнений `поле оператор значение`, объединённых
+/// через `&&`, `||` и `!`, со скобками для группировки:
+///
+/// ```text
+/// type == TRANSFER && amount > 10000 && status != SUCCESS
+/// ```
+///
+/// Поля и их типы: `tx_id`, `from_user_id`, `to_user_id`, `timestamp` —
+/// `u64`; `amount` — `i64`; `type` (или `tx_type`) и `status` —
+/// перечисления; `description` — строка. Числа сравниваются операторами
+/// `==`, `!=`, `<`, `<=`, `>`, `>=`, перечисления — только `==` и `!=`,
+/// строки — `==`, `!=` и `~` (поиск по регулярному выражению). Строки
+/// записываются в двойных кавычках, значения перечислений — без кавычек.
+/// Типы значений проверяются при разборе, ошибка указывает позицию в
+/// выражении.
+#[derive(Debug, Clone)]
+pub struct Query {
+    expr: Expr,
+}
+
+impl Query {
+    /// Разбирает выражение и проверяет типы значений
+    pub fn parse(source: &str) -> ParseResult<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = parser.parse_or()?;
+        let token = parser.peek();
+        if token.kind != TokenKind::End {
+            return Err(query_error(
+                token.position,
+                format!("ожидался конец выражения, получено {}", token.kind),
+            ));
+        }
+        Ok(Query { expr })
+    }
+
+    /// Проверяет, удовлетворяет ли транзакция выражению
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        self.expr.eval(transaction)
+    }
+}
+
+impl FromStr for Query {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Query::parse(source)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Comparison),
+}
+
+impl Expr {
+    fn eval(&self, transaction: &Transaction) -> bool {
+        match self {
+            Expr::And(left, right) => left.eval(transaction) && right.eval(transaction),
+            Expr::Or(left, right) => left.eval(transaction) || right.eval(transaction),
+            Expr::Not(inner) => !inner.eval(transaction),
+            Expr::Compare(comparison) => comparison.eval(transaction),
+        }
+    }
+}
+
+/// Сравнение поля с значением, тип которого уже проверен
+#[derive(Debug, Clone)]
+enum Comparison {
+    Unsigned(Field, CompareOp, u64),
+    Amount(CompareOp, i64),
+    Type(CompareOp, TransactionType),
+    Status(CompareOp, TransactionStatus),
+    Description(CompareOp, String),
+    DescriptionMatches(Regex),
+}
+
+impl Comparison {
+    fn eval(&self, transaction: &Transaction) -> bool {
+        match self {
+            Comparison::Unsigned(field, op, value) => {
+                let actual = match field {
+                    Field::TxId => transaction.tx_id,
+                    Field::FromUserId => transaction.from_user_id,
+                    Field::ToUserId => transaction.to_user_id,
+                    _ => transaction.timestamp,
+                };
+                op.holds(actual.cmp(value))
+            }
+            Comparison::Amount(op, value) => op.holds(transaction.amount.cmp(value)),
+            Comparison::Type(op, value) => op.holds(transaction.tx_type.cmp(value)),
+            Comparison::Status(op, value) => op.holds(transaction.status.cmp(value)),
+            Comparison::Description(op, value) => {
+                op.holds(transaction.description.as_str().cmp(value))
+            }
+            Comparison::DescriptionMatches(regex) => regex.is_match(&transaction.description),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Field {
+    TxId,
+    Type,
+    FromUserId,
+    ToUserId,
+    Amount,
+    Timestamp,
+    Status,
+    Description,
+}
+
+const FIELD_NAMES: &str =
+    "tx_id, type, from_user_id, to_user_id, amount, timestamp, status, description";
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "tx_id" => Some(Field::TxId),
+            "type" | "tx_type" => Some(Field::Type),
+            "from_user_id" => Some(Field::FromUserId),
+            "to_user_id" => Some(Field::ToUserId),
+            "amount" => Some(Field::Amount),
+            "timestamp" => Some(Field::Timestamp),
+            "status" => Some(Field::Status),
+            "description" => Some(Field::Description),
+            _ => None,
+        }
+    }
+
+    /// Тип поля для сообщений об ошибках
+    fn type_name(self) -> &'static str {
+        match self {
+            Field::TxId | Field::FromUserId | Field::ToUserId | Field::Timestamp => "u64",
+            Field::Amount => "i64",
+            Field::Type => "TransactionType",
+            Field::Status => "TransactionStatus",
+            Field::Description => "String",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+}
+
+impl CompareOp {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::Ne => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::Le => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::Ge => ordering != Ordering::Less,
+            CompareOp::Match => false,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+            CompareOp::Match => "~",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum TokenKind {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Compare(CompareOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    End,
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Ident(name) => write!(f, "`{name}`"),
+            TokenKind::Number(number) => write!(f, "число {number}"),
+            TokenKind::Str(value) => write!(f, "строка {value:?}"),
+            TokenKind::Compare(op) => write!(f, "`{}`", op.symbol()),
+            TokenKind::And => write!(f, "`&&`"),
+            TokenKind::Or => write!(f, "`||`"),
+            TokenKind::Not => write!(f, "`!`"),
+            TokenKind::LParen => write!(f, "`(`"),
+            TokenKind::RParen => write!(f, "`)`"),
+            TokenKind::End => write!(f, "конец выражения"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// Позиция первого символа, начиная с 1
+    position: usize,
+}
+
+fn query_error(position: usize, message: impl Into<String>) -> ParseError {
+    ParseError::InvalidQuery {
+        position,
+        message: message.into(),
+    }
+}
+
+fn tokenize(source: &str) -> ParseResult<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let position = i + 1;
+        let next = chars.get(i + 1).copied();
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let (kind, len) = match (c, next) {
+            ('(', _) => (TokenKind::LParen, 1),
+            (')', _) => (TokenKind::RParen, 1),
+            ('&', Some('&')) => (TokenKind::And, 2),
+            ('|', Some('|')) => (TokenKind::Or, 2),
+            ('=', Some('=')) => (TokenKind::Compare(CompareOp::Eq), 2),
+            ('!', Some('=')) => (TokenKind::Compare(CompareOp::Ne), 2),
+            ('<', Some('=')) => (TokenKind::Compare(CompareOp::Le), 2),
+            ('>', Some('=')) => (TokenKind::Compare(CompareOp::Ge), 2),
+            ('!', _) => (TokenKind::Not, 1),
+            ('<', _) => (TokenKind::Compare(CompareOp::Lt), 1),
+            ('>', _) => (TokenKind::Compare(CompareOp::Gt), 1),
+            ('~', _) => (TokenKind::Compare(CompareOp::Match), 1),
+            ('&', _) => return Err(query_error(position, "ожидалось `&&`")),
+            ('|', _) => return Err(query_error(position, "ожидалось `||`")),
+            ('=', _) => return Err(query_error(position, "ожидалось `==`")),
+            ('"', _) => lex_string(&chars[i..], position)?,
+            (c, next)
+                if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) =>
+            {
+                let len = 1 + chars[i + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit() || **c == '_')
+                    .count();
+                let number: String = chars[i..i + len].iter().filter(|c| **c != '_').collect();
+                (TokenKind::Number(number), len)
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_')
+                    .count();
+                (TokenKind::Ident(chars[i..i + len].iter().collect()), len)
+            }
+            (c, _) => return Err(query_error(position, format!("неожиданный символ `{c}`"))),
+        };
+
+        tokens.push(Token { kind, position });
+        i += len;
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        position: chars.len() + 1,
+    });
+    Ok(tokens)
+}
+
+/// Разбирает строку в кавычках; возвращает её значение и длину вместе с кавычками
+fn lex_string(chars: &[char], position: usize) -> ParseResult<(TokenKind, usize)> {
+    let mut value = String::new();
+    let mut i = 1;
+    while let Some(&c) = chars.get(i) {
+        match c {
+            '"' => return Ok((TokenKind::Str(value), i + 1)),
+            '\\' => match chars.get(i + 1) {
+                Some(&escaped @ ('"' | '\\')) => {
+                    value.push(escaped);
+                    i += 2;
+                }
+                _ => {
+                    return Err(query_error(
+                        position + i,
+                        "в строке допустимы только экранирования \\\" и \\\\",
+                    ));
+                }
+            },
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(query_error(position, "строка не закрыта кавычкой"))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].clone();
+        if token.kind != TokenKind::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn parse_or(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_and()?;
+        while self.peek().kind == TokenKind::Or {
+            self.advance();
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_unary()?;
+        while self.peek().kind == TokenKind::And {
+            self.advance();
+            let right = self.parse_unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Expr> {
+        match self.peek().kind {
+            TokenKind::Not => {
+                self.advance();
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            TokenKind::LParen => {
+                let open = self.advance();
+                let expr = self.parse_or()?;
+                let token = self.advance();
+                if token.kind != TokenKind::RParen {
+                    return Err(query_error(
+                        token.position,
+                        format!(
+                            "ожидалась `)` к скобке на позиции {}, получено {}",
+                            open.position, token.kind
+                        ),
+                    ));
+                }
+                Ok(expr)
+            }
+            _ => self.parse_comparison().map(Expr::Compare),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> ParseResult<Comparison> {
+        let token = self.advance();
+        let field = match &token.kind {
+            TokenKind::Ident(name) => Field::from_name(name).ok_or_else(|| {
+                query_error(
+                    token.position,
+                    format!("неизвестное поле `{name}`; доступные поля: {FIELD_NAMES}"),
+                )
+            })?,
+            kind => {
+                return Err(query_error(
+                    token.position,
+                    format!("ожидалось имя поля, получено {kind}"),
+                ));
+            }
+        };
+
+        let token = self.advance();
+        let op = match token.kind {
+            TokenKind::Compare(op) => op,
+            kind => {
+                return Err(query_error(
+                    token.position,
+                    format!("ожидался оператор сравнения после поля, получено {kind}"),
+                ));
+            }
+        };
+
+        let value = self.advance();
+        check_operator(field, op, token.position)?;
+        typed_comparison(field, op, value)
+    }
+}
+
+/// Проверяет, что оператор применим к типу поля
+fn check_operator(field: Field, op: CompareOp, position: usize) -> ParseResult<()> {
+    let allowed = match field {
+        Field::Type | Field::Status => matches!(op, CompareOp::Eq | CompareOp::Ne),
+        Field::Description => matches!(op, CompareOp::Eq | CompareOp::Ne | CompareOp::Match),
+        _ => op != CompareOp::Match,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(query_error(
+            position,
+            format!(
+                "оператор `{}` неприменим к полю типа {}",
+                op.symbol(),
+                field.type_name()
+            ),
+        ))
+    }
+}
+
+/// Строит сравнение, приводя значение к типу поля
+fn typed_comparison(field: Field, op: CompareOp, value: Token) -> ParseResult<Comparison> {
+    let mismatch = |expected: &str| {
+        query_error(
+            value.position,
+            format!(
+                "поле имеет тип {}, ожидалось {expected}, получено {}",
+                field.type_name(),
+                value.kind
+            ),
+        )
+    };
+    let out_of_range = |number: &str| {
+        query_error(
+            value.position,
+            format!("число {number} не помещается в тип {}", field.type_name()),
+        )
+    };
+
+    match (field, &value.kind) {
+        (Field::Amount, TokenKind::Number(number)) => number
+            .parse()
+            .map(|amount| Comparison::Amount(op, amount))
+            .map_err(|_| out_of_range(number)),
+        (Field::Amount, _) => Err(mismatch("целое число")),
+        (Field::Type, TokenKind::Ident(name) | TokenKind::Str(name)) => {
+            TransactionType::from_name(&name.to_uppercase(), UnknownCodePolicy::Preserve)
+                .map(|tx_type| Comparison::Type(op, tx_type))
+                .map_err(|_| {
+                    query_error(
+                        value.position,
+                        format!(
+                            "неизвестный тип транзакции `{name}`; допустимы DEPOSIT, TRANSFER, \
+                             WITHDRAWAL, REFUND, FEE, CHARGEBACK и UNKNOWN_<код>"
+                        ),
+                    )
+                })
+        }
+        (Field::Type, _) => Err(mismatch("имя типа транзакции")),
+        (Field::Status, TokenKind::Ident(name) | TokenKind::Str(name)) => {
+            TransactionStatus::from_name(&name.to_uppercase(), UnknownCodePolicy::Preserve)
+                .map(|status| Comparison::Status(op, status))
+                .map_err(|_| {
+                    query_error(
+                        value.position,
+                        format!(
+                            "неизвестный статус транзакции `{name}`; допустимы SUCCESS, FAILURE, \
+                             PENDING, CANCELLED, REVERSED и UNKNOWN_<код>"
+                        ),
+                    )
+                })
+        }
+        (Field::Status, _) => Err(mismatch("имя статуса транзакции")),
+        (Field::Description, TokenKind::Str(pattern)) if op == CompareOp::Match => {
+            Regex::new(pattern)
+                .map(Comparison::DescriptionMatches)
+                .map_err(|e| {
+                    query_error(
+                        value.position,
+                        format!("некорректное регулярное выражение: {e}"),
+                    )
+                })
+        }
+        (Field::Description, TokenKind::Str(text)) => Ok(Comparison::Description(op, text.clone())),
+        (Field::Description, _) => Err(mismatch("строка в двойных кавычках")),
+        (_, TokenKind::Number(number)) => number
+            .parse()
+            .map(|number| Comparison::Unsigned(field, op, number))
+            .map_err(|_| out_of_range(number)),
+        (_, _) => Err(mismatch("неотрицательное целое число")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ParseResult<Vec<Transaction>> {
+        Ok(vec![
+            Transaction::deposit(1, 20_000)
+                .tx_id(1)
+                .timestamp(100)
+                .description("Salary")
+                .build()?,
+            Transaction::transfer(1, 2, 15_000)
+                .tx_id(2)
+                .timestamp(200)
+                .status(TransactionStatus::Failure)
+                .description("Rent \"May\"")
+                .build()?,
+            Transaction::transfer(2, 3, 5_000)
+                .tx_id(3)
+                .timestamp(300)
+                .status(TransactionStatus::Pending)
+                .build()?,
+            Transaction::withdrawal(3, 1_000)
+                .tx_id(4)
+                .timestamp(400)
+                .description("ATM")
+                .build()?,
+        ])
+    }
+
+    fn ids(source: &str) -> ParseResult<Vec<u64>> {
+        let query = Query::parse(source)?;
+        Ok(sample()?
+            .iter()
+            .filter(|t| query.matches(t))
+            .map(|t| t.tx_id)
+            .collect())
+    }
+
+    fn error_position(source: &str) -> usize {
+        match Query::parse(source) {
+            Err(ParseError::InvalidQuery { position, .. }) => position,
+            other => panic!("ожидалась ошибка запроса, получено {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_query_matches() -> ParseResult<()> {
+        assert_eq!(
+            ids("type == TRANSFER && amount > 10000 && status != SUCCESS")?,
+            vec![2]
+        );
+        assert_eq!(
+            ids("type == deposit || tx_type == \"WITHDRAWAL\"")?,
+            vec![1, 4]
+        );
+        assert_eq!(ids("!(from_user_id == 1 || to_user_id == 1)")?, vec![3, 4]);
+        assert_eq!(ids("timestamp >= 200 && timestamp < 400")?, vec![2, 3]);
+        assert_eq!(ids("amount <= 5_000")?, vec![3, 4]);
+        assert_eq!(ids("description == \"Rent \\\"May\\\"\"")?, vec![2]);
+        assert_eq!(ids("description ~ \"^[A-Z]{3}$\"")?, vec![4]);
+        assert_eq!(
+            ids("amount > -1 && (tx_id == 1 || tx_id == 3) && !status == FAILURE")?,
+            vec![1, 3]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_errors() {
+        assert_eq!(error_position("amout > 5"), 1);
+        assert_eq!(error_position("amount > \"5\""), 10);
+        assert_eq!(error_position("type > TRANSFER"), 6);
+        assert_eq!(error_position("status == DONE"), 11);
+        assert_eq!(error_position("tx_id == -1"), 10);
+        assert_eq!(error_position("amount ~ \"1\""), 8);
+        assert_eq!(error_position("(amount > 5"), 12);
+        assert_eq!(error_position("amount > 5 amount"), 12);
+        assert_eq!(error_position("amount = 5"), 8);
+        assert_eq!(error_position("description == \"open"), 16);
+        assert_eq!(error_position(""), 1);
+    }
+}
@@ -0,0 +1,549 @@
+use crate::{
+    BinaryFormat, BinaryRecords, Format, ParseError, ParseResult, Transaction, UnknownCodePolicy,
+};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::iter::Peekable;
+use std::path::PathBuf;
+use tempfile::{NamedTempFile, TempPath};
+
+/// Число транзакций в одной серии внешней сортировки по умолчанию
+const DEFAULT_RUN_SIZE: usize = 100_000;
+
+/// Наибольшее число серий, которые сливаются одновременно
+const MAX_FAN_IN: usize = 64;
+
+/// Поле, по которому упорядочиваются транзакции
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SortKey {
+    TxId,
+    Timestamp,
+    Amount,
+}
+
+impl SortKey {
+    /// Сравнивает транзакции по значению поля
+    pub fn compare(self, a: &Transaction, b: &Transaction) -> Ordering {
+        match self {
+            SortKey::TxId => a.tx_id.cmp(&b.tx_id),
+            SortKey::Timestamp => a.timestamp.cmp(&b.timestamp),
+            SortKey::Amount => a.amount.cmp(&b.amount),
+        }
+    }
+}
+
+/// Порядок сортировки: поле и направление
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl SortOrder {
+    /// Порядок по возрастанию поля
+    pub fn ascending(key: SortKey) -> Self {
+        SortOrder {
+            key,
+            descending: false,
+        }
+    }
+
+    /// Порядок по убыванию поля
+    pub fn descending(key: SortKey) -> Self {
+        SortOrder {
+            key,
+            descending: true,
+        }
+    }
+
+    /// Сравнивает транзакции с учётом направления
+    pub fn compare(self, a: &Transaction, b: &Transaction) -> Ordering {
+        let ordering = self.key.compare(a, b);
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Внешняя сортировка транзакций
+///
+/// Транзакции читаются сериями по `run_size` штук. Каждая серия
+/// сортируется в памяти и, если за ней следуют другие, сбрасывается во
+/// временный файл в формате YPBankBin. Затем серии сливаются потоком,
+/// поэтому в памяти одновременно находится не больше одной серии.
+/// Сливается не больше 64 серий за раз: если серий больше,
+/// они предварительно сливаются группами в новые временные файлы, так что
+/// число открытых файлов не зависит от размера входа.
+/// Сортировка устойчива: транзакции с равным ключом остаются в исходном
+/// порядке. Временные файлы удаляются автоматически.
+#[derive(Debug, Clone)]
+pub struct ExternalSorter {
+    order: SortOrder,
+    run_size: usize,
+    temp_dir: Option<PathBuf>,
+}
+
+impl ExternalSorter {
+    /// Создаёт сортировку в указанном порядке
+    pub fn new(order: SortOrder) -> Self {
+        ExternalSorter {
+            order,
+            run_size: DEFAULT_RUN_SIZE,
+            temp_dir: None,
+        }
+    }
+
+    /// Задаёт число транзакций, сортируемых в памяти за раз
+    pub fn with_run_size(mut self, run_size: usize) -> Self {
+        self.run_size = run_size.max(1);
+        self
+    }
+
+    /// Задаёт каталог для временных файлов; по умолчанию — системный
+    pub fn with_temp_dir(mut self, temp_dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(temp_dir.into());
+        self
+    }
+
+    /// Сортирует поток транзакций
+    ///
+    /// Весь поток читается до возврата результата; ошибка чтения или
+    /// записи временных файлов возвращается сразу.
+    pub fn sort<I>(&self, records: I) -> ParseResult<MergedRecords<SortedRun>>
+    where
+        I: IntoIterator<Item = ParseResult<Transaction>>,
+    {
+        let mut spilled = Vec::new();
+        let mut buffer = Vec::new();
+
+        for transaction in records {
+            buffer.push(transaction?);
+            if buffer.len() == self.run_size {
+                buffer.sort_by(|a, b| self.order.compare(a, b));
+                spilled.push(self.spill(buffer.drain(..).map(Ok))?);
+            }
+        }
+
+        // Последняя серия остаётся в памяти и занимает одно место слияния
+        while spilled.len() >= MAX_FAN_IN {
+            spilled = self.merge_pass(spilled)?;
+        }
+
+        buffer.sort_by(|a, b| self.order.compare(a, b));
+        let mut runs = spilled
+            .into_iter()
+            .map(SortedRun::open)
+            .collect::<ParseResult<Vec<_>>>()?;
+        runs.push(SortedRun(Run::Memory(buffer.into_iter())));
+
+        Ok(MergedRecords::new(runs, self.order))
+    }
+
+    /// Сливает серии группами по `MAX_FAN_IN` в новые временные файлы
+    ///
+    /// Группы идут подряд в исходном порядке серий, поэтому слияние
+    /// остаётся устойчивым.
+    fn merge_pass(&self, spilled: Vec<TempPath>) -> ParseResult<Vec<TempPath>> {
+        let mut merged = Vec::new();
+        let mut spilled = spilled.into_iter().peekable();
+        while spilled.peek().is_some() {
+            let group = spilled
+                .by_ref()
+                .take(MAX_FAN_IN)
+                .map(SortedRun::open)
+                .collect::<ParseResult<Vec<_>>>()?;
+            merged.push(self.spill(MergedRecords::new(group, self.order))?);
+        }
+        Ok(merged)
+    }
+
+    /// Записывает упорядоченные транзакции во временный файл и закрывает его
+    fn spill<I>(&self, transactions: I) -> ParseResult<TempPath>
+    where
+        I: IntoIterator<Item = ParseResult<Transaction>>,
+    {
+        let file = match &self.temp_dir {
+            Some(dir) => NamedTempFile::new_in(dir)?,
+            None => NamedTempFile::new()?,
+        };
+        let mut writer = BufWriter::new(file);
+        spill_format().write_records(&mut writer, transactions)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        Ok(file.into_temp_path())
+    }
+}
+
+/// Формат временных файлов; неизвестные коды сохраняются без изменений
+fn spill_format() -> BinaryFormat {
+    BinaryFormat::new().with_unknown_codes(UnknownCodePolicy::Preserve)
+}
+
+/// Отсортированная серия внешней сортировки
+pub struct SortedRun(Run);
+
+enum Run {
+    Memory(std::vec::IntoIter<Transaction>),
+    /// Временный файл удаляется вместе с серией
+    File {
+        records: Box<BinaryRecords<BufReader<File>>>,
+        _path: TempPath,
+    },
+}
+
+impl SortedRun {
+    /// Открывает серию, сброшенную во временный файл
+    fn open(path: TempPath) -> ParseResult<Self> {
+        let file = File::open(&path)?;
+        Ok(SortedRun(Run::File {
+            records: Box::new(spill_format().records(BufReader::new(file))),
+            _path: path,
+        }))
+    }
+}
+
+impl Iterator for SortedRun {
+    type Item = ParseResult<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            Run::Memory(transactions) => transactions.next().map(Ok),
+            Run::File { records, .. } => records.next(),
+        }
+    }
+}
+
+/// Слияние нескольких упорядоченных потоков транзакций в один
+///
+/// Каждый поток должен быть упорядочен в том же порядке, иначе
+/// возвращается ошибка [`ParseError::UnsortedInput`]. При равных ключах
+/// первой выдаётся транзакция из потока с меньшим номером, так что
+/// слияние устойчиво. После первой ошибки итератор завершается.
+pub struct MergedRecords<I> {
+    sources: Vec<I>,
+    heap: BinaryHeap<Head>,
+    order: SortOrder,
+    started: bool,
+    done: bool,
+}
+
+impl<I> MergedRecords<I>
+where
+    I: Iterator<Item = ParseResult<Transaction>>,
+{
+    /// Создаёт слияние потоков, упорядоченных в порядке `order`
+    pub fn new(sources: Vec<I>, order: SortOrder) -> Self {
+        MergedRecords {
+            sources,
+            heap: BinaryHeap::new(),
+            order,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Берёт следующую транзакцию потока `source` в кучу
+    ///
+    /// `previous` — предыдущая транзакция того же потока; если новая
+    /// должна идти раньше неё, поток не упорядочен.
+    fn pull(&mut self, source: usize, previous: Option<&Transaction>) -> ParseResult<()> {
+        if let Some(transaction) = self.sources[source].next().transpose()? {
+            if previous.is_some_and(|previous| {
+                self.order.compare(&transaction, previous) == Ordering::Less
+            }) {
+                return Err(ParseError::UnsortedInput(transaction.tx_id));
+            }
+            self.heap.push(Head {
+                transaction,
+                source,
+                order: self.order,
+            });
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self) -> ParseResult<Option<Transaction>> {
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                self.pull(source, None)?;
+            }
+        }
+
+        match self.heap.pop() {
+            Some(head) => {
+                self.pull(head.source, Some(&head.transaction))?;
+                Ok(Some(head.transaction))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl<I> Iterator for MergedRecords<I>
+where
+    I: Iterator<Item = ParseResult<Transaction>>,
+{
+    type Item = ParseResult<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.advance().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Текущая транзакция потока в куче слияния
+struct Head {
+    transaction: Transaction,
+    source: usize,
+    order: SortOrder,
+}
+
+impl Ord for Head {
+    /// `BinaryHeap` выдаёт наибольший элемент, поэтому порядок обращён
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order
+            .compare(&self.transaction, &other.transaction)
+            .then(self.source.cmp(&other.source))
+            .reverse()
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+/// Что делать с транзакциями, у которых совпадает TX_ID, но отличается содержимое
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum DuplicatePolicy {
+    /// Оставить транзакцию, которая встретилась первой
+    #[default]
+    KeepFirst,
+    /// Оставить транзакцию, которая встретилась последней
+    KeepLast,
+    /// Вернуть ошибку [`ParseError::DuplicateTxId`]
+    Error,
+}
+
+/// Удаление повторов TX_ID из потока, упорядоченного по TX_ID
+///
+/// Повторы должны идти подряд, поэтому поток сначала сортируется по
+/// [`SortKey::TxId`] устойчивой сортировкой. Полностью совпадающие
+/// транзакции сводятся к одной, для различающихся применяется
+/// [`DuplicatePolicy`]. После первой ошибки итератор завершается.
+pub struct Deduplicated<I: Iterator> {
+    records: Peekable<I>,
+    policy: DuplicatePolicy,
+    done: bool,
+}
+
+impl<I> Deduplicated<I>
+where
+    I: Iterator<Item = ParseResult<Transaction>>,
+{
+    pub fn new(records: I, policy: DuplicatePolicy) -> Self {
+        Deduplicated {
+            records: records.peekable(),
+            policy,
+            done: false,
+        }
+    }
+
+    fn advance(&mut self) -> ParseResult<Option<Transaction>> {
+        let Some(mut kept) = self.records.next().transpose()? else {
+            return Ok(None);
+        };
+
+        while let Some(Ok(next)) = self.records.peek() {
+            if next.tx_id != kept.tx_id {
+                break;
+            }
+            if *next != kept {
+                match self.policy {
+                    DuplicatePolicy::KeepFirst => {}
+                    DuplicatePolicy::KeepLast => kept = next.clone(),
+                    DuplicatePolicy::Error => return Err(ParseError::DuplicateTxId(kept.tx_id)),
+                }
+            }
+            self.records.next();
+        }
+
+        Ok(Some(kept))
+    }
+}
+
+impl<I> Iterator for Deduplicated<I>
+where
+    I: Iterator<Item = ParseResult<Transaction>>,
+{
+    type Item = ParseResult<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.advance().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionType;
+
+    fn transaction(tx_id: u64, timestamp: u64, amount: i64) -> ParseResult<Transaction> {
+        Transaction::deposit(1, amount)
+            .tx_id(tx_id)
+            .timestamp(timestamp)
+            .build()
+    }
+
+    #[test]
+    fn test_external_sort_spills_runs() -> ParseResult<()> {
+        let records: Vec<ParseResult<Transaction>> = (0..23u64)
+            .map(|i| transaction(i, (i * 7) % 5, 100 + (i as i64 % 4)))
+            .collect();
+
+        let sorted = ExternalSorter::new(SortOrder::ascending(SortKey::Timestamp))
+            .with_run_size(4)
+            .sort(records)?
+            .collect::<ParseResult<Vec<_>>>()?;
+
+        assert_eq!(sorted.len(), 23);
+        for pair in sorted.windows(2) {
+            assert!(pair[0].timestamp <= pair[1].timestamp);
+            // устойчивость: при равном времени сохраняется исходный порядок
+            if pair[0].timestamp == pair[1].timestamp {
+                assert!(pair[0].tx_id < pair[1].tx_id);
+            }
+        }
+
+        let descending = ExternalSorter::new(SortOrder::descending(SortKey::Amount))
+            .with_run_size(5)
+            .sort(sorted.into_iter().map(Ok))?
+            .map(|t| t.map(|t| t.amount))
+            .collect::<ParseResult<Vec<_>>>()?;
+        assert!(descending.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_sort_merges_in_passes() -> ParseResult<()> {
+        // По серии на транзакцию: серий больше, чем сливается за раз
+        let count = MAX_FAN_IN as u64 * 3 + 5;
+        let records: Vec<ParseResult<Transaction>> = (0..count)
+            .map(|i| transaction(i, (i * 37) % 11, 1))
+            .collect();
+
+        let sorted = ExternalSorter::new(SortOrder::ascending(SortKey::Timestamp))
+            .with_run_size(1)
+            .sort(records)?
+            .collect::<ParseResult<Vec<_>>>()?;
+
+        assert_eq!(sorted.len() as u64, count);
+        for pair in sorted.windows(2) {
+            assert!(
+                (pair[0].timestamp, pair[0].tx_id) < (pair[1].timestamp, pair[1].tx_id),
+                "порядок или устойчивость нарушены"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_sort_keeps_unknown_codes() -> ParseResult<()> {
+        let mut unknown = transaction(2, 0, 100)?;
+        unknown.tx_type = TransactionType::Unknown(42);
+        let records = vec![Ok(unknown.clone()), transaction(1, 0, 100)];
+
+        let sorted = ExternalSorter::new(SortOrder::ascending(SortKey::TxId))
+            .with_run_size(1)
+            .sort(records)?
+            .collect::<ParseResult<Vec<_>>>()?;
+
+        assert_eq!(sorted[1], unknown);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_sorted_sources() -> ParseResult<()> {
+        let first = vec![transaction(1, 100, 1), transaction(2, 300, 1)];
+        let second = vec![transaction(3, 100, 1), transaction(4, 200, 1)];
+        let order = SortOrder::ascending(SortKey::Timestamp);
+
+        let merged = MergedRecords::new(vec![first.into_iter(), second.into_iter()], order)
+            .map(|t| t.map(|t| t.tx_id))
+            .collect::<ParseResult<Vec<_>>>()?;
+        assert_eq!(merged, vec![1, 3, 4, 2]);
+
+        let unsorted = vec![transaction(1, 300, 1), transaction(2, 100, 1)];
+        let result =
+            MergedRecords::new(vec![unsorted.into_iter()], order).collect::<ParseResult<Vec<_>>>();
+        assert!(matches!(result, Err(ParseError::UnsortedInput(2))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deduplicate_policies() -> ParseResult<()> {
+        let first = transaction(1, 100, 500)?;
+        let conflicting = transaction(1, 200, 700)?;
+        let records = || {
+            vec![
+                Ok(first.clone()),
+                Ok(first.clone()),
+                Ok(conflicting.clone()),
+                transaction(2, 100, 100),
+            ]
+        };
+        let dedupe = |policy| {
+            Deduplicated::new(records().into_iter(), policy).collect::<ParseResult<Vec<_>>>()
+        };
+
+        let kept = dedupe(DuplicatePolicy::KeepFirst)?;
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0], first);
+
+        let kept = dedupe(DuplicatePolicy::KeepLast)?;
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0], conflicting);
+
+        assert!(matches!(
+            dedupe(DuplicatePolicy::Error),
+            Err(ParseError::DuplicateTxId(1))
+        ));
+
+        let identical = vec![Ok(first.clone()), Ok(first.clone())];
+        let kept = Deduplicated::new(identical.into_iter(), DuplicatePolicy::Error)
+            .collect::<ParseResult<Vec<_>>>()?;
+        assert_eq!(kept, vec![first]);
+
+        Ok(())
+    }
+}
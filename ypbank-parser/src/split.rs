@@ -0,0 +1,255 @@
+use crate::{ParseError, ParseResult, Transaction};
+
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// Шаблон пути для разбиения транзакций на части
+///
+/// Подстановки в фигурных скобках вычисляются по каждой транзакции,
+/// поэтому набор подстановок и задаёт разбиение:
+///
+/// - `{yyyy}`, `{mm}`, `{dd}` — год, месяц и день `TIMESTAMP` по UTC;
+/// - `{type}` — тип транзакции, например `TRANSFER`;
+/// - `{bucket}` — номер корзины пользователя от `0` до `buckets - 1`;
+///   пользователь — отправитель, а для пополнений — получатель;
+/// - `{chunk}` — номер части из `chunk_size` записей подряд, начиная
+///   с `0001`.
+///
+/// Например, `out/{yyyy}/{mm}/{dd}.bin` разбивает транзакции по дням.
+#[derive(Debug, Clone)]
+pub struct PathTemplate {
+    parts: Vec<Part>,
+    buckets: Option<u64>,
+    chunk_size: Option<u64>,
+    records: u64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Part {
+    Literal(String),
+    Year,
+    Month,
+    Day,
+    Type,
+    Bucket,
+    Chunk,
+}
+
+impl PathTemplate {
+    /// Разбирает шаблон
+    ///
+    /// `buckets` нужен для `{bucket}`, `chunk_size` — для `{chunk}`;
+    /// указывать их без соответствующей подстановки нельзя, как и
+    /// шаблон без подстановок: все транзакции попали бы в один файл.
+    pub fn parse(
+        template: &str,
+        buckets: Option<u64>,
+        chunk_size: Option<u64>,
+    ) -> ParseResult<Self> {
+        let invalid = |message: String| ParseError::InvalidTemplate(message);
+
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid(format!("незакрытая скобка в {template}")))?;
+            let part = match &rest[start + 1..start + end] {
+                "yyyy" => Part::Year,
+                "mm" => Part::Month,
+                "dd" => Part::Day,
+                "type" => Part::Type,
+                "bucket" => Part::Bucket,
+                "chunk" => Part::Chunk,
+                name => {
+                    return Err(invalid(format!(
+                        "неизвестная подстановка {{{name}}}; допустимы {{yyyy}}, {{mm}}, \
+                         {{dd}}, {{type}}, {{bucket}}, {{chunk}}"
+                    )));
+                }
+            };
+            parts.push(part);
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        if parts.iter().all(|part| matches!(part, Part::Literal(_))) {
+            return Err(invalid(format!("в шаблоне {template} нет подстановок")));
+        }
+        let uses = |wanted: Part| parts.contains(&wanted);
+        match (uses(Part::Bucket), buckets) {
+            (true, None | Some(0)) => {
+                return Err(invalid(
+                    "для {bucket} нужно положительное число корзин".to_string(),
+                ));
+            }
+            (false, Some(_)) => {
+                return Err(invalid(
+                    "число корзин задано, но {bucket} не используется".to_string(),
+                ));
+            }
+            _ => {}
+        }
+        match (uses(Part::Chunk), chunk_size) {
+            (true, None | Some(0)) => {
+                return Err(invalid(
+                    "для {chunk} нужен положительный размер части".to_string(),
+                ));
+            }
+            (false, Some(_)) => {
+                return Err(invalid(
+                    "размер части задан, но {chunk} не используется".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(PathTemplate {
+            parts,
+            buckets,
+            chunk_size,
+            records: 0,
+        })
+    }
+
+    /// Путь для очередной транзакции
+    ///
+    /// Транзакции нужно передавать в порядке входного потока: номер части
+    /// `{chunk}` считается по числу уже переданных записей.
+    pub fn render(&mut self, transaction: &Transaction) -> String {
+        let (year, month, day) = civil_date(transaction.timestamp);
+        let index = self.records;
+        self.records += 1;
+
+        let mut path = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => path.push_str(text),
+                Part::Year => path.push_str(&format!("{year:04}")),
+                Part::Month => path.push_str(&format!("{month:02}")),
+                Part::Day => path.push_str(&format!("{day:02}")),
+                Part::Type => path.push_str(&transaction.tx_type.to_string()),
+                Part::Bucket => {
+                    let buckets = self.buckets.unwrap_or(1);
+                    path.push_str(&(user_hash(owner(transaction)) % buckets).to_string());
+                }
+                Part::Chunk => {
+                    let size = self.chunk_size.unwrap_or(1);
+                    path.push_str(&format!("{:04}", index / size + 1));
+                }
+            }
+        }
+        path
+    }
+}
+
+/// Пользователь, к которому относится транзакция: отправитель, а если
+/// он внешний — получатель
+fn owner(transaction: &Transaction) -> u64 {
+    if transaction.from_user_id != 0 {
+        transaction.from_user_id
+    } else {
+        transaction.to_user_id
+    }
+}
+
+/// Перемешивание splitmix64: стабильно между версиями и платформами,
+/// в отличие от `DefaultHasher`
+fn user_hash(user_id: u64) -> u64 {
+    let mut x = user_id.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// Год, месяц и день по UTC для времени в миллисекундах Unix
+fn civil_date(timestamp: u64) -> (u64, u64, u64) {
+    // Алгоритм days-to-civil Говарда Хиннанта; эпоха сдвинута на 0000-03-01
+    let days = timestamp / MILLIS_PER_DAY + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(1_633_036_860_000), (2021, 9, 30));
+        assert_eq!(civil_date(951_782_400_000), (2000, 2, 29));
+        assert_eq!(civil_date(1_709_251_199_999), (2024, 2, 29));
+        assert_eq!(civil_date(1_735_689_600_000), (2025, 1, 1));
+    }
+
+    #[test]
+    fn test_render_template() -> ParseResult<()> {
+        let deposit = Transaction::deposit(7, 100)
+            .timestamp(1_633_036_860_000)
+            .build()?;
+        let withdrawal = Transaction::withdrawal(7, 50)
+            .timestamp(1_735_689_600_000)
+            .build()?;
+
+        let mut by_day = PathTemplate::parse("out/{yyyy}/{mm}/{dd}-{type}.bin", None, None)?;
+        assert_eq!(by_day.render(&deposit), "out/2021/09/30-DEPOSIT.bin");
+        assert_eq!(by_day.render(&withdrawal), "out/2025/01/01-WITHDRAWAL.bin");
+
+        let mut by_bucket = PathTemplate::parse("users-{bucket}.csv", Some(16), None)?;
+        assert_eq!(by_bucket.render(&deposit), by_bucket.render(&withdrawal));
+
+        let mut chunks = PathTemplate::parse("part-{chunk}.txt", None, Some(2))?;
+        let paths: Vec<String> = (0..5).map(|_| chunks.render(&deposit)).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "part-0001.txt",
+                "part-0001.txt",
+                "part-0002.txt",
+                "part-0002.txt",
+                "part-0003.txt"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        for (template, buckets, chunk_size) in [
+            ("out.bin", None, None),
+            ("out/{yyyy.bin", None, None),
+            ("out/{week}.bin", None, None),
+            ("out/{bucket}.bin", None, None),
+            ("out/{bucket}.bin", Some(0), None),
+            ("out/{dd}.bin", Some(4), None),
+            ("out/{chunk}.bin", None, None),
+            ("out/{dd}.bin", None, Some(100)),
+        ] {
+            assert!(
+                matches!(
+                    PathTemplate::parse(template, buckets, chunk_size),
+                    Err(ParseError::InvalidTemplate(_))
+                ),
+                "{template}"
+            );
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{Ledger, ParseError, ParseResult, Transaction, TransactionType};
+use std::collections::BTreeMap;
+
+/// Строка выписки
+#[derive(Debug, PartialEq, Clone)]
+pub struct StatementEntry {
+    pub transaction: Transaction,
+    /// Изменение баланса пользователя; ноль для неисполненных транзакций
+    pub delta: i64,
+    /// Баланс после транзакции
+    pub running_balance: i64,
+}
+
+/// Итоги выписки по одному типу транзакций
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct TypeTotal {
+    pub count: usize,
+    /// Сумма изменений баланса по транзакциям этого типа
+    pub amount: i64,
+}
+
+/// Выписка по счёту пользователя за период
+///
+/// Балансы считаются так же, как в [`Ledger`]: учитываются только
+/// исполненные транзакции. В выписку попадают все транзакции, где
+/// пользователь указан отправителем или получателем, включая
+/// неисполненные и транзакции с неизвестным типом или статусом, — у них
+/// нулевое изменение баланса.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Statement {
+    pub user_id: u64,
+    /// Начало периода включительно
+    pub from: Option<u64>,
+    /// Конец периода включительно
+    pub to: Option<u64>,
+    pub opening_balance: i64,
+    pub closing_balance: i64,
+    pub entries: Vec<StatementEntry>,
+    pub totals: BTreeMap<TransactionType, TypeTotal>,
+}
+
+impl Statement {
+    /// Строит выписку пользователя по набору транзакций
+    ///
+    /// Транзакции упорядочиваются по времени; при равном времени
+    /// сохраняется исходный порядок.
+    pub fn build(
+        transactions: &[Transaction],
+        user_id: u64,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> ParseResult<Self> {
+        let mut sorted: Vec<&Transaction> = transactions
+            .iter()
+            .filter(|t| t.from_user_id == user_id || t.to_user_id == user_id)
+            .filter(|t| to.is_none_or(|to| t.timestamp <= to))
+            .collect();
+        sorted.sort_by_key(|t| t.timestamp);
+
+        let in_period = |t: &Transaction| from.is_none_or(|from| t.timestamp >= from);
+        let overflow = |tx_id| ParseError::BalanceOverflow { user_id, tx_id };
+
+        let mut ledger = Ledger::new();
+        let mut entries = Vec::new();
+        let mut totals: BTreeMap<TransactionType, TypeTotal> = BTreeMap::new();
+        let mut opening_balance = 0;
+
+        for transaction in sorted {
+            let before = ledger.balance(user_id).settled;
+            ledger.apply(transaction)?;
+            let after = ledger.balance(user_id).settled;
+
+            if !in_period(transaction) {
+                opening_balance = after;
+                continue;
+            }
+
+            let delta = after
+                .checked_sub(before)
+                .ok_or_else(|| overflow(transaction.tx_id))?;
+            let total = totals.entry(transaction.tx_type).or_default();
+            total.count += 1;
+            total.amount = total
+                .amount
+                .checked_add(delta)
+                .ok_or_else(|| overflow(transaction.tx_id))?;
+
+            entries.push(StatementEntry {
+                transaction: transaction.clone(),
+                delta,
+                running_balance: after,
+            });
+        }
+
+        let closing_balance = entries
+            .last()
+            .map_or(opening_balance, |entry| entry.running_balance);
+
+        Ok(Statement {
+            user_id,
+            from,
+            to,
+            opening_balance,
+            closing_balance,
+            entries,
+            totals,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionStatus;
+
+    #[test]
+    fn test_statement_balances() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(1, 1_000)
+                .tx_id(1)
+                .timestamp(100)
+                .build()?,
+            Transaction::transfer(1, 2, 300)
+                .tx_id(3)
+                .timestamp(300)
+                .build()?,
+            Transaction::deposit(1, 200)
+                .tx_id(2)
+                .timestamp(200)
+                .build()?,
+            Transaction::withdrawal(1, 50)
+                .tx_id(4)
+                .timestamp(400)
+                .status(TransactionStatus::Failure)
+                .build()?,
+            Transaction::deposit(2, 70)
+                .tx_id(5)
+                .timestamp(250)
+                .build()?,
+            Transaction::withdrawal(1, 100)
+                .tx_id(6)
+                .timestamp(900)
+                .build()?,
+        ];
+
+        let statement = Statement::build(&transactions, 1, Some(200), Some(500))?;
+
+        assert_eq!(statement.opening_balance, 1_000);
+        assert_eq!(statement.closing_balance, 900);
+
+        let rows: Vec<(u64, i64, i64)> = statement
+            .entries
+            .iter()
+            .map(|e| (e.transaction.tx_id, e.delta, e.running_balance))
+            .collect();
+        assert_eq!(rows, vec![(2, 200, 1_200), (3, -300, 900), (4, 0, 900)]);
+
+        assert_eq!(
+            statement.totals[&TransactionType::Deposit],
+            TypeTotal {
+                count: 1,
+                amount: 200
+            }
+        );
+        assert_eq!(statement.totals[&TransactionType::Withdrawal].count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_statement_empty_period() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(1, 500)
+                .tx_id(1)
+                .timestamp(100)
+                .build()?,
+        ];
+
+        let statement = Statement::build(&transactions, 1, Some(1_000), None)?;
+        assert!(statement.entries.is_empty());
+        assert_eq!(statement.opening_balance, 500);
+        assert_eq!(statement.closing_balance, 500);
+
+        Ok(())
+    }
+}
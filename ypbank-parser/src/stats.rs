@@ -0,0 +1,199 @@
+use crate::{Transaction, TransactionStatus, TransactionType};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Сводная статистика по набору транзакций
+///
+/// Накапливается за один проход: транзакции подаются по одной через
+/// [`Stats::push`]. Для медианы хранятся все суммы, по 8 байт на запись;
+/// они сортируются на месте при первом запросе медианы.
+/// Суммы считаются в `i128`, поэтому не переполняются. Пользователь `0`
+/// — внешний контрагент и в статистику по пользователям не входит.
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    count: u64,
+    by_type: BTreeMap<TransactionType, u64>,
+    by_status: BTreeMap<TransactionStatus, u64>,
+    amounts: Vec<i64>,
+    /// `amounts` отсортированы, и медиану можно взять по индексу
+    amounts_sorted: bool,
+    amount_sum: i128,
+    amount_range: Option<(i64, i64)>,
+    timestamp_range: Option<(u64, u64)>,
+    users: HashSet<u64>,
+    sent: HashMap<u64, i128>,
+    received: HashMap<u64, i128>,
+}
+
+impl Stats {
+    /// Создаёт пустую статистику
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Учитывает одну транзакцию
+    pub fn push(&mut self, transaction: &Transaction) {
+        self.count += 1;
+        *self.by_type.entry(transaction.tx_type).or_default() += 1;
+        *self.by_status.entry(transaction.status).or_default() += 1;
+
+        let amount = transaction.amount;
+        self.amounts.push(amount);
+        self.amounts_sorted = false;
+        self.amount_sum += i128::from(amount);
+        self.amount_range = Some(match self.amount_range {
+            Some((min, max)) => (min.min(amount), max.max(amount)),
+            None => (amount, amount),
+        });
+
+        let timestamp = transaction.timestamp;
+        self.timestamp_range = Some(match self.timestamp_range {
+            Some((first, last)) => (first.min(timestamp), last.max(timestamp)),
+            None => (timestamp, timestamp),
+        });
+
+        let amount = i128::from(transaction.amount);
+        if transaction.from_user_id != 0 {
+            self.users.insert(transaction.from_user_id);
+            *self.sent.entry(transaction.from_user_id).or_default() += amount;
+        }
+        if transaction.to_user_id != 0 {
+            self.users.insert(transaction.to_user_id);
+            *self.received.entry(transaction.to_user_id).or_default() += amount;
+        }
+    }
+
+    /// Число транзакций
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Число транзакций по типам
+    pub fn by_type(&self) -> &BTreeMap<TransactionType, u64> {
+        &self.by_type
+    }
+
+    /// Число транзакций по статусам
+    pub fn by_status(&self) -> &BTreeMap<TransactionStatus, u64> {
+        &self.by_status
+    }
+
+    pub fn amount_sum(&self) -> i128 {
+        self.amount_sum
+    }
+
+    pub fn amount_min(&self) -> Option<i64> {
+        self.amount_range.map(|(min, _)| min)
+    }
+
+    pub fn amount_max(&self) -> Option<i64> {
+        self.amount_range.map(|(_, max)| max)
+    }
+
+    pub fn amount_mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.amount_sum as f64 / self.count as f64)
+    }
+
+    /// Медиана сумм; для чётного числа записей — среднее двух средних
+    ///
+    /// Сортирует накопленные суммы на месте; повторный вызов без новых
+    /// транзакций их не пересортировывает.
+    pub fn amount_median(&mut self) -> Option<f64> {
+        if !self.amounts_sorted {
+            self.amounts.sort_unstable();
+            self.amounts_sorted = true;
+        }
+        let amounts = &self.amounts;
+        let middle = amounts.len() / 2;
+        match amounts.len() {
+            0 => None,
+            len if len % 2 == 1 => Some(amounts[middle] as f64),
+            _ => Some((amounts[middle - 1] as f64 + amounts[middle] as f64) / 2.0),
+        }
+    }
+
+    /// Наименьший и наибольший TIMESTAMP
+    pub fn timestamp_range(&self) -> Option<(u64, u64)> {
+        self.timestamp_range
+    }
+
+    /// Число различных пользователей среди отправителей и получателей
+    pub fn distinct_users(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Пользователи с наибольшей суммой отправленных средств
+    pub fn top_senders(&self, limit: usize) -> Vec<(u64, i128)> {
+        top(&self.sent, limit)
+    }
+
+    /// Пользователи с наибольшей суммой полученных средств
+    pub fn top_receivers(&self, limit: usize) -> Vec<(u64, i128)> {
+        top(&self.received, limit)
+    }
+}
+
+impl<'a> Extend<&'a Transaction> for Stats {
+    fn extend<I: IntoIterator<Item = &'a Transaction>>(&mut self, transactions: I) {
+        for transaction in transactions {
+            self.push(transaction);
+        }
+    }
+}
+
+/// Первые `limit` пользователей по убыванию суммы, при равенстве — по id
+fn top(totals: &HashMap<u64, i128>, limit: usize) -> Vec<(u64, i128)> {
+    let mut totals: Vec<(u64, i128)> = totals.iter().map(|(&id, &sum)| (id, sum)).collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    totals.truncate(limit);
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseResult;
+
+    #[test]
+    fn test_stats() -> ParseResult<()> {
+        let transactions = vec![
+            Transaction::deposit(1, 100).timestamp(300).build()?,
+            Transaction::transfer(1, 2, 40).timestamp(100).build()?,
+            Transaction::transfer(2, 3, 10)
+                .timestamp(200)
+                .status(TransactionStatus::Failure)
+                .build()?,
+            Transaction::withdrawal(3, 70).timestamp(400).build()?,
+        ];
+
+        let mut stats = Stats::new();
+        stats.extend(&transactions);
+
+        assert_eq!(stats.count(), 4);
+        assert_eq!(stats.by_type()[&TransactionType::Transfer], 2);
+        assert_eq!(stats.by_status()[&TransactionStatus::Failure], 1);
+        assert_eq!(stats.amount_sum(), 220);
+        assert_eq!(stats.amount_min(), Some(10));
+        assert_eq!(stats.amount_max(), Some(100));
+        assert_eq!(stats.amount_mean(), Some(55.0));
+        assert_eq!(stats.amount_median(), Some(55.0));
+        assert_eq!(stats.timestamp_range(), Some((100, 400)));
+        assert_eq!(stats.distinct_users(), 3);
+        assert_eq!(stats.top_senders(2), vec![(3, 70), (1, 40)]);
+        assert_eq!(stats.top_receivers(1), vec![(1, 100)]);
+
+        // Новая транзакция после медианы снова учитывается
+        stats.push(&Transaction::deposit(4, 5).build()?);
+        assert_eq!(stats.amount_min(), Some(5));
+        assert_eq!(stats.amount_median(), Some(40.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_empty() {
+        let mut stats = Stats::new();
+        assert_eq!(stats.amount_mean(), None);
+        assert_eq!(stats.amount_median(), None);
+        assert_eq!(stats.timestamp_range(), None);
+    }
+}
@@ -1,5 +1,7 @@
-use crate::error::ParseError;
+use crate::error::{ParseError, ParseResult};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Финансовая транзакция
 #[derive(Debug, PartialEq, Clone)]
@@ -12,14 +14,251 @@ pub struct Transaction {
     pub timestamp: u64,
     pub status: TransactionStatus,
     pub description: String,
+    /// Исходная транзакция, к которой относится возврат или сторно
+    pub parent_tx_id: Option<u64>,
+    /// Дополнительные атрибуты партнёров: канал, терминал, MCC и т. п.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Transaction {
+    /// Создаёт построитель транзакции произвольного типа
+    pub fn builder() -> TransactionBuilder {
+        TransactionBuilder::default()
+    }
+
+    /// Создаёт построитель пополнения счёта пользователя `to_user_id`
+    pub fn deposit(to_user_id: u64, amount: i64) -> TransactionBuilder {
+        Self::builder()
+            .tx_type(TransactionType::Deposit)
+            .to_user_id(to_user_id)
+            .amount(amount)
+    }
+
+    /// Создаёт построитель перевода от `from_user_id` к `to_user_id`
+    pub fn transfer(from_user_id: u64, to_user_id: u64, amount: i64) -> TransactionBuilder {
+        Self::builder()
+            .tx_type(TransactionType::Transfer)
+            .from_user_id(from_user_id)
+            .to_user_id(to_user_id)
+            .amount(amount)
+    }
+
+    /// Создаёт построитель списания со счёта пользователя `from_user_id`
+    pub fn withdrawal(from_user_id: u64, amount: i64) -> TransactionBuilder {
+        Self::builder()
+            .tx_type(TransactionType::Withdrawal)
+            .from_user_id(from_user_id)
+            .amount(amount)
+    }
+
+    /// Проверяет согласованность полей транзакции
+    ///
+    /// Сумма должна быть положительной, а нулевой контрагент допускается
+    /// только там, где его требует тип: отправитель у пополнения и
+    /// получатель у списания и комиссии. Для неизвестных типов
+    /// контрагенты не проверяются. Метаданные должны быть представимы во
+    /// всех форматах, см. [`TransactionBuilder::metadata`].
+    pub fn validate(&self) -> ParseResult<()> {
+        if self.amount <= 0 {
+            return Err(ParseError::InvalidTransaction(format!(
+                "сумма должна быть положительной: {}",
+                self.amount
+            )));
+        }
+        for (key, value) in &self.metadata {
+            if !metadata_allowed(key, value) {
+                return Err(ParseError::InvalidTransaction(format!(
+                    "недопустимые метаданные {key:?}={value:?}"
+                )));
+            }
+        }
+
+        let (from_is_zero, to_is_zero) = match self.tx_type {
+            TransactionType::Deposit => (true, false),
+            TransactionType::Transfer | TransactionType::Refund | TransactionType::Chargeback => {
+                (false, false)
+            }
+            TransactionType::Withdrawal | TransactionType::Fee => (false, true),
+            TransactionType::Unknown(_) => return Ok(()),
+        };
+
+        if (self.from_user_id == 0) != from_is_zero {
+            return Err(ParseError::InvalidTransaction(format!(
+                "недопустимый FROM_USER_ID {} для {}",
+                self.from_user_id, self.tx_type
+            )));
+        }
+        if (self.to_user_id == 0) != to_is_zero {
+            return Err(ParseError::InvalidTransaction(format!(
+                "недопустимый TO_USER_ID {} для {}",
+                self.to_user_id, self.tx_type
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Построитель транзакции
+///
+/// Статус по умолчанию — `SUCCESS`, время — текущее, в миллисекундах
+/// с начала эпохи Unix. Перед созданием транзакция проверяется через
+/// [`Transaction::validate`].
+#[derive(Debug, Default, Clone)]
+pub struct TransactionBuilder {
+    tx_id: u64,
+    tx_type: Option<TransactionType>,
+    from_user_id: u64,
+    to_user_id: u64,
+    amount: i64,
+    timestamp: Option<u64>,
+    status: Option<TransactionStatus>,
+    description: String,
+    parent_tx_id: Option<u64>,
+    metadata: BTreeMap<String, String>,
+}
+
+impl TransactionBuilder {
+    pub fn tx_id(mut self, tx_id: u64) -> Self {
+        self.tx_id = tx_id;
+        self
+    }
+
+    pub fn tx_type(mut self, tx_type: TransactionType) -> Self {
+        self.tx_type = Some(tx_type);
+        self
+    }
+
+    pub fn from_user_id(mut self, from_user_id: u64) -> Self {
+        self.from_user_id = from_user_id;
+        self
+    }
+
+    pub fn to_user_id(mut self, to_user_id: u64) -> Self {
+        self.to_user_id = to_user_id;
+        self
+    }
+
+    pub fn amount(mut self, amount: i64) -> Self {
+        self.amount = amount;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn status(mut self, status: TransactionStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    pub fn parent_tx_id(mut self, parent_tx_id: u64) -> Self {
+        self.parent_tx_id = Some(parent_tx_id);
+        self
+    }
+
+    /// Добавляет атрибут метаданных, заменяя прежнее значение ключа
+    ///
+    /// Ключ не может быть пустым и содержать пробелы, `,`, `:` и `"`;
+    /// значение не может быть пустым, содержать перевод строки и начинаться
+    /// или заканчиваться пробелом. Нарушение обнаруживается в
+    /// [`TransactionBuilder::build`].
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Создаёт транзакцию, проверив согласованность полей
+    pub fn build(self) -> ParseResult<Transaction> {
+        let tx_type = self
+            .tx_type
+            .ok_or_else(|| ParseError::MissingField("TX_TYPE".to_string()))?;
+
+        let transaction = Transaction {
+            tx_id: self.tx_id,
+            tx_type,
+            from_user_id: self.from_user_id,
+            to_user_id: self.to_user_id,
+            amount: self.amount,
+            timestamp: self.timestamp.unwrap_or_else(now_millis),
+            status: self.status.unwrap_or(TransactionStatus::Success),
+            description: self.description,
+            parent_tx_id: self.parent_tx_id,
+            metadata: self.metadata,
+        };
+        transaction.validate()?;
+
+        Ok(transaction)
+    }
+}
+
+/// Допустима ли пара метаданных
+///
+/// Ключ становится частью имени поля или колонки в текстовом и CSV
+/// форматах, поэтому не может быть пустым и содержать пробелы и
+/// разделители. Значение не может быть пустым: пустая ячейка CSV означает
+/// отсутствие ключа. Оно также не может содержать переводы строк, а также
+/// начинаться или заканчиваться пробелом: при чтении такие пробелы
+/// отбрасываются.
+pub(crate) fn metadata_allowed(key: &str, value: &str) -> bool {
+    let bad_key = key.is_empty()
+        || key
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, ',' | ':' | '"'));
+    let bad_value = value.is_empty() || value.trim() != value || value.contains(['\n', '\r']);
+    !bad_key && !bad_value
+}
+
+/// Текущее время в миллисекундах с начала эпохи Unix
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
 }
 
 /// Тип финансовой транзакции
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
 pub enum TransactionType {
     Deposit,
     Transfer,
     Withdrawal,
+    /// Возврат средств по ранее проведённой операции
+    Refund,
+    /// Комиссия банка
+    Fee,
+    /// Опротестование операции плательщиком
+    Chargeback,
+    /// Код типа, неизвестный этой версии библиотеки
+    Unknown(u8),
+}
+
+impl TransactionType {
+    /// Преобразует бинарный код в тип с учётом политики для неизвестных кодов
+    pub fn from_code(code: u8, policy: UnknownCodePolicy) -> ParseResult<Self> {
+        match (Self::try_from(code), policy) {
+            (Err(_), UnknownCodePolicy::Preserve) => Ok(TransactionType::Unknown(code)),
+            (result, _) => result,
+        }
+    }
+
+    /// Преобразует строковое имя в тип с учётом политики для неизвестных кодов
+    ///
+    /// При `Preserve` принимается также форма `UNKNOWN_<код>`, в которой
+    /// текстовые форматы сохраняют неизвестный тип.
+    pub fn from_name(name: &str, policy: UnknownCodePolicy) -> ParseResult<Self> {
+        match (policy, unknown_code(name)) {
+            (UnknownCodePolicy::Preserve, Some(code)) => Self::from_code(code, policy),
+            _ => Self::try_from(name),
+        }
+    }
 }
 
 impl fmt::Display for TransactionType {
@@ -28,6 +267,10 @@ impl fmt::Display for TransactionType {
             TransactionType::Deposit => write!(f, "DEPOSIT"),
             TransactionType::Transfer => write!(f, "TRANSFER"),
             TransactionType::Withdrawal => write!(f, "WITHDRAWAL"),
+            TransactionType::Refund => write!(f, "REFUND"),
+            TransactionType::Fee => write!(f, "FEE"),
+            TransactionType::Chargeback => write!(f, "CHARGEBACK"),
+            TransactionType::Unknown(code) => write!(f, "{UNKNOWN_PREFIX}{code}"),
         }
     }
 }
@@ -40,6 +283,9 @@ impl TryFrom<&str> for TransactionType {
             "DEPOSIT" => Ok(TransactionType::Deposit),
             "TRANSFER" => Ok(TransactionType::Transfer),
             "WITHDRAWAL" => Ok(TransactionType::Withdrawal),
+            "REFUND" => Ok(TransactionType::Refund),
+            "FEE" => Ok(TransactionType::Fee),
+            "CHARGEBACK" => Ok(TransactionType::Chargeback),
             _ => Err(ParseError::InvalidTransactionType(value.to_string())),
         }
     }
@@ -53,6 +299,9 @@ impl TryFrom<u8> for TransactionType {
             0 => Ok(TransactionType::Deposit),
             1 => Ok(TransactionType::Transfer),
             2 => Ok(TransactionType::Withdrawal),
+            3 => Ok(TransactionType::Refund),
+            4 => Ok(TransactionType::Fee),
+            5 => Ok(TransactionType::Chargeback),
             _ => Err(ParseError::InvalidTransactionType(value.to_string())),
         }
     }
@@ -64,16 +313,47 @@ impl From<TransactionType> for u8 {
             TransactionType::Deposit => 0,
             TransactionType::Transfer => 1,
             TransactionType::Withdrawal => 2,
+            TransactionType::Refund => 3,
+            TransactionType::Fee => 4,
+            TransactionType::Chargeback => 5,
+            TransactionType::Unknown(code) => code,
         }
     }
 }
 
 /// Статус транзакции
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
 pub enum TransactionStatus {
     Success,
     Failure,
     Pending,
+    /// Операция отменена до исполнения
+    Cancelled,
+    /// Исполненная операция сторнирована
+    Reversed,
+    /// Код статуса, неизвестный этой версии библиотеки
+    Unknown(u8),
+}
+
+impl TransactionStatus {
+    /// Преобразует бинарный код в статус с учётом политики для неизвестных кодов
+    pub fn from_code(code: u8, policy: UnknownCodePolicy) -> ParseResult<Self> {
+        match (Self::try_from(code), policy) {
+            (Err(_), UnknownCodePolicy::Preserve) => Ok(TransactionStatus::Unknown(code)),
+            (result, _) => result,
+        }
+    }
+
+    /// Преобразует строковое имя в статус с учётом политики для неизвестных кодов
+    ///
+    /// При `Preserve` принимается также форма `UNKNOWN_<код>`, в которой
+    /// текстовые форматы сохраняют неизвестный статус.
+    pub fn from_name(name: &str, policy: UnknownCodePolicy) -> ParseResult<Self> {
+        match (policy, unknown_code(name)) {
+            (UnknownCodePolicy::Preserve, Some(code)) => Self::from_code(code, policy),
+            _ => Self::try_from(name),
+        }
+    }
 }
 
 impl fmt::Display for TransactionStatus {
@@ -82,6 +362,9 @@ impl fmt::Display for TransactionStatus {
             TransactionStatus::Success => write!(f, "SUCCESS"),
             TransactionStatus::Failure => write!(f, "FAILURE"),
             TransactionStatus::Pending => write!(f, "PENDING"),
+            TransactionStatus::Cancelled => write!(f, "CANCELLED"),
+            TransactionStatus::Reversed => write!(f, "REVERSED"),
+            TransactionStatus::Unknown(code) => write!(f, "{UNKNOWN_PREFIX}{code}"),
         }
     }
 }
@@ -94,6 +377,8 @@ impl TryFrom<&str> for TransactionStatus {
             "SUCCESS" => Ok(TransactionStatus::Success),
             "FAILURE" => Ok(TransactionStatus::Failure),
             "PENDING" => Ok(TransactionStatus::Pending),
+            "CANCELLED" => Ok(TransactionStatus::Cancelled),
+            "REVERSED" => Ok(TransactionStatus::Reversed),
             _ => Err(ParseError::InvalidTransactionStatus(value.to_string())),
         }
     }
@@ -107,6 +392,8 @@ impl TryFrom<u8> for TransactionStatus {
             0 => Ok(TransactionStatus::Success),
             1 => Ok(TransactionStatus::Failure),
             2 => Ok(TransactionStatus::Pending),
+            3 => Ok(TransactionStatus::Cancelled),
+            4 => Ok(TransactionStatus::Reversed),
             _ => Err(ParseError::InvalidTransactionStatus(value.to_string())),
         }
     }
@@ -118,10 +405,39 @@ impl From<TransactionStatus> for u8 {
             TransactionStatus::Success => 0,
             TransactionStatus::Failure => 1,
             TransactionStatus::Pending => 2,
+            TransactionStatus::Cancelled => 3,
+            TransactionStatus::Reversed => 4,
+            TransactionStatus::Unknown(code) => code,
         }
     }
 }
 
+/// Префикс строковой формы неизвестного кода, например `UNKNOWN_17`
+const UNKNOWN_PREFIX: &str = "UNKNOWN_";
+
+/// Политика обработки кодов типа и статуса, которых нет в этой версии
+///
+/// `Reject` сохраняет прежнее поведение: такая запись считается ошибкой.
+/// `Preserve` позволяет старым читателям пережить новых писателей: код
+/// сохраняется в варианте `Unknown` и записывается обратно без изменений.
+///
+/// Полностью это работает только для YPBankBin, где тип и статус хранятся
+/// кодом. В текстовом формате и CSV они записываются именем, а имя, которого
+/// нет в этой версии, не сопоставить с кодом, поэтому оно остаётся ошибкой
+/// и при `Preserve`. Сохраняется лишь форма `UNKNOWN_<код>`, в которой
+/// текстовые форматы пишут уже прочитанные неизвестные коды.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum UnknownCodePolicy {
+    #[default]
+    Reject,
+    Preserve,
+}
+
+/// Извлекает код из строковой формы `UNKNOWN_<код>`
+fn unknown_code(name: &str) -> Option<u8> {
+    name.strip_prefix(UNKNOWN_PREFIX)?.parse().ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,7 +455,10 @@ mod tests {
         assert_eq!(TransactionType::try_from(0u8).unwrap(), TransactionType::Deposit);
         assert_eq!(TransactionType::try_from(1u8).unwrap(), TransactionType::Transfer);
         assert_eq!(TransactionType::try_from(2u8).unwrap(), TransactionType::Withdrawal);
-        assert!(TransactionType::try_from(3u8).is_err());
+        assert_eq!(TransactionType::try_from(3u8).unwrap(), TransactionType::Refund);
+        assert_eq!(TransactionType::try_from(4u8).unwrap(), TransactionType::Fee);
+        assert_eq!(TransactionType::try_from(5u8).unwrap(), TransactionType::Chargeback);
+        assert!(TransactionType::try_from(6u8).is_err());
     }
 
     #[test]
@@ -150,4 +469,84 @@ mod tests {
     }
 
     // Аналогично для TransactionStatus
+
+    #[test]
+    fn test_transaction_status_codes() {
+        for status in [
+            TransactionStatus::Success,
+            TransactionStatus::Failure,
+            TransactionStatus::Pending,
+            TransactionStatus::Cancelled,
+            TransactionStatus::Reversed,
+        ] {
+            let code: u8 = status.into();
+            assert_eq!(TransactionStatus::try_from(code).unwrap(), status);
+            assert_eq!(
+                TransactionStatus::try_from(status.to_string().as_str()).unwrap(),
+                status
+            );
+        }
+        assert!(TransactionStatus::try_from(5u8).is_err());
+    }
+
+    #[test]
+    fn test_unknown_code_policy() {
+        assert!(TransactionType::from_code(200, UnknownCodePolicy::Reject).is_err());
+        let preserved = TransactionType::from_code(200, UnknownCodePolicy::Preserve).unwrap();
+        assert_eq!(preserved, TransactionType::Unknown(200));
+        assert_eq!(Into::<u8>::into(preserved), 200);
+        assert_eq!(preserved.to_string(), "UNKNOWN_200");
+
+        assert!(TransactionStatus::from_name("UNKNOWN_9", UnknownCodePolicy::Reject).is_err());
+        assert_eq!(
+            TransactionStatus::from_name("UNKNOWN_9", UnknownCodePolicy::Preserve).unwrap(),
+            TransactionStatus::Unknown(9)
+        );
+        assert_eq!(
+            TransactionType::from_name("UNKNOWN_4", UnknownCodePolicy::Preserve).unwrap(),
+            TransactionType::Fee
+        );
+    }
+
+    #[test]
+    fn test_builder_shortcuts() -> ParseResult<()> {
+        let deposit = Transaction::deposit(42, 100)
+            .tx_id(1)
+            .timestamp(1000)
+            .build()?;
+        assert_eq!(deposit.tx_type, TransactionType::Deposit);
+        assert_eq!(deposit.from_user_id, 0);
+        assert_eq!(deposit.to_user_id, 42);
+        assert_eq!(deposit.status, TransactionStatus::Success);
+
+        let withdrawal = Transaction::withdrawal(42, 50).build()?;
+        assert_eq!(withdrawal.to_user_id, 0);
+        assert!(withdrawal.timestamp > 0);
+
+        let transfer = Transaction::transfer(1, 2, 10)
+            .status(TransactionStatus::Pending)
+            .description("rent")
+            .build()?;
+        assert_eq!((transfer.from_user_id, transfer.to_user_id), (1, 2));
+        assert_eq!(transfer.description, "rent");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_invalid_combinations() {
+        assert!(Transaction::deposit(42, 0).build().is_err());
+        assert!(
+            Transaction::deposit(42, 100)
+                .from_user_id(7)
+                .build()
+                .is_err()
+        );
+        assert!(Transaction::withdrawal(0, 100).build().is_err());
+        assert!(Transaction::transfer(1, 0, 100).build().is_err());
+        assert!(matches!(
+            Transaction::builder().amount(1).build(),
+            Err(ParseError::MissingField(field)) if field == "TX_TYPE"
+        ));
+    }
 }
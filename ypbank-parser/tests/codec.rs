@@ -0,0 +1,84 @@
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use ypbank_parser::{BinaryCodec, ParseError, ParseResult, Transaction};
+
+fn transactions() -> ParseResult<Vec<Transaction>> {
+    (1..=1000)
+        .map(|i| {
+            Transaction::transfer(i % 10 + 1, i % 7 + 1, i as i64)
+                .tx_id(i)
+                .timestamp(1633036800000 + i)
+                .description(format!("Перевод №{i}"))
+                .metadata("channel", "api")
+                .build()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_codec_over_loopback() -> ParseResult<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    let sent = transactions()?;
+
+    let server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await?;
+        FramedRead::new(socket, BinaryCodec::new())
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<ParseResult<Vec<_>>>()
+    });
+
+    let mut client = FramedWrite::new(TcpStream::connect(address).await?, BinaryCodec::new());
+    for transaction in &sent {
+        client.feed(transaction).await?;
+    }
+    SinkExt::<&Transaction>::close(&mut client).await?;
+
+    let received = server.await.expect("сервер завершился с паникой")?;
+    assert_eq!(received, sent);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_codec_rejects_bad_stream() -> ParseResult<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+
+    let server = tokio::spawn(async move {
+        let mut errors = Vec::new();
+        for _ in 0..2 {
+            let (socket, _) = listener.accept().await?;
+            let mut frames = FramedRead::new(socket, BinaryCodec::new().with_max_frame_size(1024));
+            while let Some(frame) = frames.next().await {
+                if let Err(e) = frame {
+                    errors.push(e);
+                }
+            }
+        }
+        Ok::<_, ParseError>(errors)
+    });
+
+    // Чужой протокол
+    let mut socket = TcpStream::connect(address).await?;
+    socket.write_all(b"GET / HTTP/1.1\r\n\r\n").await?;
+    socket.shutdown().await?;
+    // Заявленный размер записи больше предела
+    let mut socket = TcpStream::connect(address).await?;
+    socket.write_all(b"YPBN\x00\x10\x00\x00").await?;
+    socket.shutdown().await?;
+
+    let errors = server.await.expect("сервер завершился с паникой")?;
+    assert!(matches!(errors[0], ParseError::InvalidMagic));
+    assert!(matches!(
+        errors[1],
+        ParseError::FrameTooLarge {
+            size: 0x100000,
+            max: 1024
+        }
+    ));
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use ypbank_parser::{BinaryFormat, Format, ParseResult, PathTemplate, Transaction};
+
+/// Журнал транзакций на диске, разбитый по дням
+///
+/// Файлы журнала — записи YPBankBin без заголовка и итога, поэтому в них
+/// можно дописывать, а читать их можно конвертером и библиотекой как
+/// обычные бинарные файлы. Файл для транзакции выбирается шаблоном пути по
+/// её `TIMESTAMP`, так что новый день начинает новый файл.
+pub struct Journal {
+    dir: PathBuf,
+    template: PathTemplate,
+    format: BinaryFormat,
+}
+
+/// Файл, в который пакет уже дописан, и его прежний размер
+struct Appended {
+    path: PathBuf,
+    len: u64,
+}
+
+impl Journal {
+    pub fn new(dir: PathBuf, template: PathTemplate) -> Self {
+        Journal {
+            dir,
+            template,
+            format: BinaryFormat::new(),
+        }
+    }
+
+    /// Дописывает пакет и сбрасывает файлы на диск
+    ///
+    /// Пакет записывается целиком или не записывается вовсе: при ошибке
+    /// уже дописанные файлы обрезаются до прежнего размера.
+    pub fn append(&mut self, transactions: &[Transaction]) -> ParseResult<()> {
+        // Группы в порядке появления путей, чтобы порядок записей сохранился
+        let mut groups: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+        for transaction in transactions {
+            let path = self.dir.join(self.template.render(transaction));
+            let index = match groups.iter().position(|(p, _)| *p == path) {
+                Some(index) => index,
+                None => {
+                    groups.push((path, Vec::new()));
+                    groups.len() - 1
+                }
+            };
+            self.format
+                .write_to(&mut groups[index].1, std::slice::from_ref(transaction))?;
+        }
+
+        let mut appended = Vec::new();
+        for (path, bytes) in &groups {
+            if let Err(e) = append_file(path, bytes, &mut appended) {
+                rollback(&appended);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Дописывает байты в конец файла и ждёт записи на диск
+fn append_file(path: &Path, bytes: &[u8], appended: &mut Vec<Appended>) -> ParseResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    appended.push(Appended {
+        path: path.to_path_buf(),
+        len: file.metadata()?.len(),
+    });
+    file.write_all(bytes)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Возвращает файлы к размеру до записи пакета
+fn rollback(appended: &[Appended]) {
+    for file in appended {
+        let result = OpenOptions::new()
+            .write(true)
+            .open(&file.path)
+            .and_then(|f| f.set_len(file.len));
+        if let Err(e) = result {
+            eprintln!(
+                "Не удалось откатить {}: {e}; в конце файла мог остаться неполный пакет",
+                file.path.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2021-10-01 00:00:00 UTC
+    const DAY: u64 = 1633046400000;
+
+    fn journal(dir: &Path) -> ParseResult<Journal> {
+        let template = PathTemplate::parse("{yyyy}-{mm}-{dd}.bin", None, None)?;
+        Ok(Journal::new(dir.to_path_buf(), template))
+    }
+
+    #[test]
+    fn test_append_rolls_back_on_error() -> ParseResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut journal = journal(dir.path())?;
+        let first = Transaction::deposit(1, 100)
+            .tx_id(1)
+            .timestamp(DAY)
+            .build()?;
+        journal.append(std::slice::from_ref(&first))?;
+        let path = dir.path().join("2021-10-01.bin");
+        let len = fs::metadata(&path)?.len();
+
+        // Файл следующего дня не открыть: на его месте каталог
+        fs::create_dir(dir.path().join("2021-10-02.bin"))?;
+        let batch = [
+            Transaction::deposit(1, 200)
+                .tx_id(2)
+                .timestamp(DAY + 1)
+                .build()?,
+            Transaction::deposit(1, 300)
+                .tx_id(3)
+                .timestamp(DAY + 86_400_000)
+                .build()?,
+        ];
+        assert!(journal.append(&batch).is_err());
+
+        assert_eq!(fs::metadata(&path)?.len(), len);
+        let read = BinaryFormat::new().read_from(fs::File::open(&path)?)?;
+        assert_eq!(read, [first]);
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+mod journal;
+mod protocol;
+
+use clap::builder::RangedU64ValueParser;
+use clap::{ArgGroup, Parser, ValueEnum};
+use journal::Journal;
+use protocol::{BatchReader, BinaryBatches, NdjsonBatches};
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use ypbank_parser::{ParseResult, PathTemplate};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Приём потоков транзакций в журнал на диске")]
+#[command(group(ArgGroup::new("address").required(true).args(["listen", "unix"])))]
+struct Args {
+    /// Адрес TCP, например `127.0.0.1:7070`
+    #[arg(short, long)]
+    listen: Option<String>,
+
+    /// Путь к Unix-сокету
+    #[arg(long)]
+    unix: Option<PathBuf>,
+
+    /// Формат потока от производителей
+    #[arg(long, default_value = "binary")]
+    protocol: Protocol,
+
+    /// Каталог журнала
+    #[arg(short, long)]
+    dir: PathBuf,
+
+    /// Путь файла журнала внутри каталога; подстановки как у `split` в конвертере
+    #[arg(long, default_value = "{yyyy}-{mm}-{dd}.bin")]
+    layout: String,
+
+    /// Наибольший размер пакета в байтах
+    #[arg(long = "max-batch-size", default_value_t = 64 * 1024 * 1024)]
+    max_batch_size: usize,
+
+    /// Наибольшее число одновременных соединений; остальные ждут в очереди
+    #[arg(
+        long = "max-connections",
+        default_value_t = 64,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_connections: usize,
+
+    /// Сколько секунд ждать данных от производителя; 0 — ждать без ограничения
+    #[arg(long = "read-timeout", default_value_t = 300)]
+    read_timeout: u64,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Protocol {
+    /// Пакеты YPBankBin, каждый завершается итогом
+    Binary,
+    /// Транзакция JSON в строке, пакеты разделяются пустой строкой
+    Ndjson,
+}
+
+/// Общие настройки обработчиков соединений
+struct Server {
+    protocol: Protocol,
+    max_batch_size: usize,
+    read_timeout: Option<Duration>,
+    journal: Mutex<Journal>,
+    /// Число свободных мест для соединений
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Место, занятое соединением; освобождается при закрытии соединения
+struct Slot(Arc<Server>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Соединение, для которого можно ограничить время ожидания данных
+trait Connection {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Server {
+    /// Ждёт, пока освободится место для соединения
+    fn acquire(self: &Arc<Self>) -> Slot {
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        while *free == 0 {
+            free = self.released.wait(free).unwrap_or_else(|e| e.into_inner());
+        }
+        *free -= 1;
+        Slot(Arc::clone(self))
+    }
+
+    /// Обслуживает соединение: читает пакеты, записывает принятые в журнал
+    /// и отвечает на каждый строкой `OK <число записей>` или `ERR <причина>`
+    fn serve<S>(&self, stream: S) -> ParseResult<()>
+    where
+        for<'a> &'a S: Read + Write,
+    {
+        let reader = BufReader::new(&stream);
+        let mut batches: Box<dyn BatchReader + '_> = match self.protocol {
+            Protocol::Binary => Box::new(BinaryBatches::new(reader, self.max_batch_size)),
+            Protocol::Ndjson => Box::new(NdjsonBatches::new(reader, self.max_batch_size)),
+        };
+
+        loop {
+            let batch = match batches.next_batch() {
+                Ok(Some(batch)) => batch,
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    // Граница пакетов потеряна: сообщаем причину и закрываем
+                    writeln!(&stream, "ERR {e}")?;
+                    return Err(e);
+                }
+            };
+
+            let result = batch.and_then(|transactions| {
+                self.journal
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .append(&transactions)?;
+                Ok(transactions.len())
+            });
+            match result {
+                Ok(count) => writeln!(&stream, "OK {count}")?,
+                Err(e) => writeln!(&stream, "ERR {e}")?,
+            }
+        }
+    }
+}
+
+/// Принимает соединения и обслуживает каждое в отдельном потоке
+///
+/// Пока заняты все места, новые соединения не принимаются и ждут в очереди
+/// сокета. Производитель, который молчит дольше `--read-timeout`, получает
+/// `ERR` и отключается.
+fn accept<S, I>(server: Arc<Server>, mut incoming: I)
+where
+    S: Connection + Send + 'static,
+    for<'a> &'a S: Read + Write,
+    I: Iterator<Item = io::Result<S>>,
+{
+    loop {
+        let slot = server.acquire();
+        let Some(stream) = incoming.next() else {
+            return;
+        };
+        let stream = stream.and_then(|s| s.set_read_timeout(server.read_timeout).map(|_| s));
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Ошибка при приёме соединения: {e}");
+                continue;
+            }
+        };
+        thread::spawn(move || {
+            if let Err(e) = slot.0.serve(stream) {
+                eprintln!("Соединение закрыто из-за ошибки: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+fn listen_unix(server: Arc<Server>, path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    // Сокет от прошлого запуска мешает bind; обычный файл не трогаем
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    eprintln!("Ожидание соединений на {}", path.display());
+    accept(server, listener.incoming());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen_unix(_server: Arc<Server>, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix-сокеты не поддерживаются на этой платформе",
+    ))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let template = PathTemplate::parse(&args.layout, None, None)?;
+    std::fs::create_dir_all(&args.dir)?;
+    let server = Arc::new(Server {
+        protocol: args.protocol,
+        max_batch_size: args.max_batch_size,
+        read_timeout: (args.read_timeout > 0).then(|| Duration::from_secs(args.read_timeout)),
+        journal: Mutex::new(Journal::new(args.dir, template)),
+        free: Mutex::new(args.max_connections),
+        released: Condvar::new(),
+    });
+
+    match (&args.listen, &args.unix) {
+        (Some(address), _) => {
+            let listener = TcpListener::bind(address)?;
+            eprintln!("Ожидание соединений на {}", listener.local_addr()?);
+            accept(server, listener.incoming());
+        }
+        (None, Some(path)) => listen_unix(server, path)?,
+        (None, None) => unreachable!("clap требует --listen или --unix"),
+    }
+    Ok(())
+}
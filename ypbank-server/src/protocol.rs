@@ -0,0 +1,231 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, ErrorKind, Read};
+use ypbank_parser::{
+    BinaryFormat, Format, FormatKind, ParseError, ParseResult, TrailerPolicy, Transaction,
+    TransactionStatus, TransactionType,
+};
+
+/// Магическое число итога YPBankBin: итог завершает пакет
+const TRAILER_MAGIC: [u8; 4] = *b"YPBT";
+
+/// Транзакции пакета или причина, по которой пакет отклонён
+pub type Batch = ParseResult<Vec<Transaction>>;
+
+/// Чтение пакетов из соединения производителя
+///
+/// Внешняя ошибка означает, что поток испорчен и соединение нужно закрыть;
+/// ошибка внутри [`Batch`] отклоняет только этот пакет.
+pub trait BatchReader {
+    /// Следующий пакет; `None` — производитель закрыл соединение между пакетами
+    fn next_batch(&mut self) -> ParseResult<Option<Batch>>;
+}
+
+/// Пакеты YPBankBin: необязательный заголовок, записи и обязательный итог
+///
+/// Пакет — то, что пишет `BinaryFormat::new().with_trailer(true)`.
+/// Элементы копятся до итога, после чего пакет разбирается целиком, так
+/// что итог и число записей из заголовка сверяются для каждого пакета.
+pub struct BinaryBatches<R> {
+    reader: R,
+    format: BinaryFormat,
+    max_size: usize,
+}
+
+impl<R: Read> BinaryBatches<R> {
+    pub fn new(reader: R, max_size: usize) -> Self {
+        BinaryBatches {
+            reader,
+            format: BinaryFormat::new().with_trailer_policy(TrailerPolicy::Require),
+            max_size,
+        }
+    }
+
+    /// Читает префикс элемента: магическое число и размер тела;
+    /// `None` — источник закончился на границе элементов
+    fn read_prefix(&mut self) -> io::Result<Option<[u8; 8]>> {
+        let mut prefix = [0u8; 8];
+        let mut filled = 0;
+        while filled < prefix.len() {
+            match self.reader.read(&mut prefix[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(count) => filled += count,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Some(prefix))
+    }
+
+    /// Разбирает накопленный пакет и проверяет каждую транзакцию
+    fn decode(&self, batch: &[u8]) -> Batch {
+        let transactions = self.format.read_from(batch)?;
+        for transaction in &transactions {
+            transaction.validate()?;
+        }
+        Ok(transactions)
+    }
+}
+
+impl<R: Read> BatchReader for BinaryBatches<R> {
+    fn next_batch(&mut self) -> ParseResult<Option<Batch>> {
+        let mut batch = Vec::new();
+        loop {
+            let Some(prefix) = self.read_prefix()? else {
+                if batch.is_empty() {
+                    return Ok(None);
+                }
+                return Err(ParseError::InvalidFormat(
+                    "Соединение закрыто до итога пакета".to_string(),
+                ));
+            };
+            if FormatKind::detect(&prefix) != Some(FormatKind::Binary) {
+                return Err(ParseError::InvalidMagic);
+            }
+
+            let size = u32::from_be_bytes([prefix[4], prefix[5], prefix[6], prefix[7]]) as usize;
+            let total = batch.len() + prefix.len() + size;
+            if total > self.max_size {
+                return Err(ParseError::FrameTooLarge {
+                    size: total,
+                    max: self.max_size,
+                });
+            }
+
+            batch.extend_from_slice(&prefix);
+            let read = (&mut self.reader)
+                .take(size as u64)
+                .read_to_end(&mut batch)?;
+            if read != size {
+                return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+            }
+            if prefix[..4] == TRAILER_MAGIC {
+                return Ok(Some(self.decode(&batch)));
+            }
+        }
+    }
+}
+
+/// Пакеты NDJSON: по транзакции в строке, пакет завершается пустой
+/// строкой или концом соединения
+pub struct NdjsonBatches<R> {
+    reader: R,
+    max_size: usize,
+    line_number: u64,
+}
+
+impl<R: BufRead> NdjsonBatches<R> {
+    pub fn new(reader: R, max_size: usize) -> Self {
+        NdjsonBatches {
+            reader,
+            max_size,
+            line_number: 0,
+        }
+    }
+
+    /// Читает строку, не больше оставшегося размера пакета; `false` —
+    /// соединение закрыто
+    fn read_line(&mut self, line: &mut String, used: usize) -> ParseResult<bool> {
+        line.clear();
+        let limit = self.max_size.saturating_sub(used) as u64 + 1;
+        let count = (&mut self.reader).take(limit).read_line(line)?;
+        if used + count > self.max_size {
+            return Err(ParseError::FrameTooLarge {
+                size: used + count,
+                max: self.max_size,
+            });
+        }
+        self.line_number += 1;
+        Ok(count > 0)
+    }
+}
+
+impl<R: BufRead> BatchReader for NdjsonBatches<R> {
+    fn next_batch(&mut self) -> ParseResult<Option<Batch>> {
+        let mut line = String::new();
+        let mut used = 0;
+        let mut transactions = Vec::new();
+        let mut error = None;
+        let mut started = false;
+
+        while self.read_line(&mut line, used)? {
+            used += line.len();
+            if line.trim().is_empty() {
+                if started {
+                    break;
+                }
+                continue;
+            }
+            started = true;
+            // Пакет с ошибкой дочитывается до конца, чтобы не потерять границу
+            if error.is_none() {
+                match parse_line(&line) {
+                    Ok(transaction) => transactions.push(transaction),
+                    Err(e) => {
+                        error = Some(ParseError::InvalidFormat(format!(
+                            "строка {}: {e}",
+                            self.line_number
+                        )));
+                    }
+                }
+            }
+        }
+
+        if !started {
+            return Ok(None);
+        }
+        Ok(Some(match error {
+            Some(e) => Err(e),
+            None => Ok(transactions),
+        }))
+    }
+}
+
+/// Транзакция в NDJSON; поля называются как в текстовом формате, но
+/// в нижнем регистре
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonTransaction {
+    tx_id: u64,
+    tx_type: String,
+    #[serde(default)]
+    from_user_id: u64,
+    #[serde(default)]
+    to_user_id: u64,
+    amount: i64,
+    timestamp: Option<u64>,
+    status: Option<String>,
+    #[serde(default)]
+    description: String,
+    parent_tx_id: Option<u64>,
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+/// Разбирает строку NDJSON и проверяет транзакцию построителем
+fn parse_line(line: &str) -> ParseResult<Transaction> {
+    let json: JsonTransaction = serde_json::from_str(line)
+        .map_err(|e| ParseError::InvalidFormat(format!("некорректный JSON: {e}")))?;
+
+    let mut builder = Transaction::builder()
+        .tx_id(json.tx_id)
+        .tx_type(TransactionType::try_from(json.tx_type.as_str())?)
+        .from_user_id(json.from_user_id)
+        .to_user_id(json.to_user_id)
+        .amount(json.amount)
+        .description(json.description);
+    if let Some(timestamp) = json.timestamp {
+        builder = builder.timestamp(timestamp);
+    }
+    if let Some(status) = json.status {
+        builder = builder.status(TransactionStatus::try_from(status.as_str())?);
+    }
+    if let Some(parent_tx_id) = json.parent_tx_id {
+        builder = builder.parent_tx_id(parent_tx_id);
+    }
+    for (key, value) in json.metadata {
+        builder = builder.metadata(key, value);
+    }
+    builder.build()
+}
@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::process::{Child, ChildStderr, Command, Stdio};
+use std::time::Duration;
+use tempfile::TempDir;
+use ypbank_parser::{BinaryFormat, Format, ParseResult, Transaction};
+
+/// 2021-10-01 00:00:00 UTC
+const DAY: u64 = 1633046400000;
+
+/// Запущенный сервер на свободном порту loopback
+struct Server {
+    child: Child,
+    /// Держим stderr открытым, чтобы сообщения сервера не упирались в закрытый канал
+    _stderr: BufReader<ChildStderr>,
+    address: String,
+    dir: TempDir,
+}
+
+impl Server {
+    fn start(args: &[&str]) -> ParseResult<Self> {
+        let dir = tempfile::tempdir()?;
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ypbank-server"))
+            .args(["--listen", "127.0.0.1:0", "--dir"])
+            .arg(dir.path())
+            .args(args)
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Первая строка stderr: «Ожидание соединений на <адрес>»
+        let mut stderr = BufReader::new(child.stderr.take().expect("stderr перехвачен"));
+        let mut line = String::new();
+        stderr.read_line(&mut line)?;
+        let address = line
+            .trim()
+            .rsplit(' ')
+            .next()
+            .expect("адрес в сообщении сервера")
+            .to_string();
+
+        Ok(Server {
+            child,
+            _stderr: stderr,
+            address,
+            dir,
+        })
+    }
+
+    fn connect(&self) -> ParseResult<Client> {
+        let stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+        Ok(Client {
+            replies: BufReader::new(stream.try_clone()?),
+            stream,
+        })
+    }
+
+    fn journal(&self, name: &str) -> ParseResult<Vec<Transaction>> {
+        BinaryFormat::new().read_from(File::open(self.dir.path().join(name))?)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    replies: BufReader<TcpStream>,
+}
+
+impl Client {
+    fn send(&mut self, data: &[u8]) -> ParseResult<String> {
+        self.stream.write_all(data)?;
+        self.reply()
+    }
+
+    fn reply(&mut self) -> ParseResult<String> {
+        let mut line = String::new();
+        self.replies.read_line(&mut line)?;
+        Ok(line.trim_end().to_string())
+    }
+
+    /// Сервер закрыл соединение: дальше читать нечего
+    fn is_closed(&mut self) -> ParseResult<bool> {
+        let mut rest = Vec::new();
+        self.replies.read_to_end(&mut rest)?;
+        Ok(rest.is_empty())
+    }
+}
+
+fn transactions(first: u64, count: u64, timestamp: u64) -> ParseResult<Vec<Transaction>> {
+    (first..first + count)
+        .map(|i| {
+            Transaction::transfer(i % 10 + 1, i % 7 + 1, i as i64)
+                .tx_id(i)
+                .timestamp(timestamp + i)
+                .description(format!("Перевод №{i}"))
+                .metadata("channel", "api")
+                .build()
+        })
+        .collect()
+}
+
+fn batch(transactions: &[Transaction]) -> ParseResult<Vec<u8>> {
+    let mut buffer = Vec::new();
+    BinaryFormat::new()
+        .with_trailer(true)
+        .write_to(&mut buffer, transactions)?;
+    Ok(buffer)
+}
+
+/// Начало итоговой записи пакета
+fn trailer_start(batch: &[u8]) -> usize {
+    batch
+        .windows(4)
+        .rposition(|window| window == b"YPBT")
+        .expect("итог в пакете")
+}
+
+#[test]
+fn test_server_binary_batches() -> ParseResult<()> {
+    let server = Server::start(&[])?;
+    let mut client = server.connect()?;
+
+    let first = transactions(1, 3, DAY - 1000)?;
+    let second = transactions(4, 2, DAY)?;
+    assert_eq!(client.send(&batch(&first)?)?, "OK 3");
+
+    // Записи одного пакета с итогом другого: пакет отклонён, соединение живо
+    let mut mismatched = batch(&first)?;
+    let other = batch(&second)?;
+    mismatched.truncate(trailer_start(&mismatched));
+    mismatched.extend_from_slice(&other[trailer_start(&other)..]);
+    assert!(client.send(&mismatched)?.starts_with("ERR "));
+
+    assert_eq!(client.send(&batch(&second)?)?, "OK 2");
+    client.stream.shutdown(Shutdown::Write)?;
+    assert!(client.is_closed()?);
+
+    // Каждый день в своём файле, отклонённый пакет не записан
+    assert_eq!(server.journal("2021-09-30.bin")?, first);
+    assert_eq!(server.journal("2021-10-01.bin")?, second);
+    Ok(())
+}
+
+#[test]
+fn test_server_ndjson_batches() -> ParseResult<()> {
+    let server = Server::start(&["--protocol", "ndjson"])?;
+    let mut client = server.connect()?;
+
+    let batch = format!(
+        "{}\n{}\n\n",
+        r#"{"tx_id": 1, "tx_type": "DEPOSIT", "to_user_id": 5, "amount": 100, "timestamp": 1633046400000}"#,
+        r#"{"tx_id": 2, "tx_type": "WITHDRAWAL", "from_user_id": 5, "amount": 40, "timestamp": 1633046400001, "metadata": {"channel": "app"}}"#,
+    );
+    assert_eq!(client.send(batch.as_bytes())?, "OK 2");
+
+    let invalid = r#"{"tx_id": 3, "tx_type": "DEPOSIT", "amount": 1, "metadata": {"": "x"}}"#;
+    assert!(
+        client
+            .send(format!("{invalid}\n\n").as_bytes())?
+            .starts_with("ERR ")
+    );
+
+    client.stream.shutdown(Shutdown::Write)?;
+    assert!(client.is_closed()?);
+    let journal = server.journal("2021-10-01.bin")?;
+    assert_eq!(journal.len(), 2);
+    assert_eq!(journal[1].metadata["channel"], "app");
+    Ok(())
+}
+
+#[test]
+fn test_server_closes_connection_on_lost_boundary() -> ParseResult<()> {
+    let server = Server::start(&["--max-batch-size", "256"])?;
+
+    let mut client = server.connect()?;
+    assert!(client.send(b"XXXX\0\0\0\0")?.starts_with("ERR "));
+    assert!(client.is_closed()?);
+
+    let mut client = server.connect()?;
+    let oversized = batch(&transactions(1, 20, DAY)?)?;
+    assert!(oversized.len() > 256);
+    assert!(client.send(&oversized)?.starts_with("ERR "));
+    assert!(client.is_closed()?);
+    Ok(())
+}
+
+#[test]
+fn test_server_limits_connections() -> ParseResult<()> {
+    let server = Server::start(&["--max-connections", "1", "--read-timeout", "1"])?;
+    let data = batch(&transactions(1, 1, DAY)?)?;
+
+    // Второе соединение ждёт, пока первое занимает единственное место
+    let mut first = server.connect()?;
+    assert_eq!(first.send(&data)?, "OK 1");
+    let mut second = server.connect()?;
+    second.stream.write_all(&data)?;
+    second
+        .stream
+        .set_read_timeout(Some(Duration::from_millis(200)))?;
+    assert!(second.reply().is_err());
+
+    // Молчащее первое соединение отключается по таймауту, и место переходит второму
+    assert!(first.reply()?.starts_with("ERR "));
+    second
+        .stream
+        .set_read_timeout(Some(Duration::from_secs(10)))?;
+    assert_eq!(second.reply()?, "OK 1");
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use clap::{Parser, ValueEnum};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use ypbank_parser::{BinaryFormat, CsvFormat, Format, ParseResult, Stats, TextFormat};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Сводная статистика по файлу транзакций")]
+struct Args {
+    /// Входной файл
+    #[arg(short, long)]
+    input: String,
+
+    /// Формат входного файла
+    #[arg(long = "input-format")]
+    input_format: FormatType,
+
+    /// Число крупнейших отправителей и получателей в отчёте
+    #[arg(long, default_value_t = 5)]
+    top: usize,
+
+    /// Формат отчёта
+    #[arg(long = "output-format", default_value = "table")]
+    output_format: ReportFormat,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum FormatType {
+    Binary,
+    Csv,
+    Text,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ReportFormat {
+    Table,
+    Json,
+}
+
+/// Собирает статистику за один проход по файлу
+fn collect_stats<F: Format, R: Read>(format: F, reader: R) -> ParseResult<Stats> {
+    let mut stats = Stats::new();
+    for transaction in format.records(reader) {
+        stats.push(&transaction?);
+    }
+    Ok(stats)
+}
+
+fn write_table<W: Write>(mut writer: W, stats: &mut Stats, top: usize) -> io::Result<()> {
+    writeln!(writer, "Записей: {}", stats.count())?;
+
+    writeln!(writer, "По типам:")?;
+    for (tx_type, count) in stats.by_type() {
+        writeln!(writer, "  {:<12} {:>12}", tx_type.to_string(), count)?;
+    }
+    writeln!(writer, "По статусам:")?;
+    for (status, count) in stats.by_status() {
+        writeln!(writer, "  {:<12} {:>12}", status.to_string(), count)?;
+    }
+
+    writeln!(writer, "Суммы:")?;
+    writeln!(writer, "  {:<12} {:>24}", "сумма", stats.amount_sum())?;
+    writeln!(
+        writer,
+        "  {:<12} {:>24}",
+        "минимум",
+        optional(stats.amount_min())
+    )?;
+    writeln!(
+        writer,
+        "  {:<12} {:>24}",
+        "максимум",
+        optional(stats.amount_max())
+    )?;
+    writeln!(
+        writer,
+        "  {:<12} {:>24}",
+        "среднее",
+        optional(stats.amount_mean())
+    )?;
+    writeln!(
+        writer,
+        "  {:<12} {:>24}",
+        "медиана",
+        optional(stats.amount_median())
+    )?;
+
+    if let Some((first, last)) = stats.timestamp_range() {
+        writeln!(writer, "TIMESTAMP: {} — {}", first, last)?;
+    }
+    writeln!(writer, "Пользователей: {}", stats.distinct_users())?;
+
+    writeln!(writer, "Крупнейшие отправители:")?;
+    for (user_id, amount) in stats.top_senders(top) {
+        writeln!(writer, "  {:>20} {:>24}", user_id, amount)?;
+    }
+    writeln!(writer, "Крупнейшие получатели:")?;
+    for (user_id, amount) in stats.top_receivers(top) {
+        writeln!(writer, "  {:>20} {:>24}", user_id, amount)?;
+    }
+
+    Ok(())
+}
+
+fn write_json<W: Write>(mut writer: W, stats: &mut Stats, top: usize) -> io::Result<()> {
+    let users = |users: Vec<(u64, i128)>| {
+        users
+            .iter()
+            .map(|(user_id, amount)| {
+                format!("{{\"user_id\": {}, \"amount\": {}}}", user_id, amount)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let (first, last) = stats.timestamp_range().unzip();
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"count\": {},", stats.count())?;
+    writeln!(
+        writer,
+        "  \"by_type\": {{{}}},",
+        json_counts(stats.by_type())
+    )?;
+    writeln!(
+        writer,
+        "  \"by_status\": {{{}}},",
+        json_counts(stats.by_status())
+    )?;
+    writeln!(
+        writer,
+        "  \"amount\": {{\"sum\": {}, \"min\": {}, \"max\": {}, \"mean\": {}, \"median\": {}}},",
+        stats.amount_sum(),
+        json(stats.amount_min()),
+        json(stats.amount_max()),
+        json(stats.amount_mean()),
+        json(stats.amount_median())
+    )?;
+    writeln!(
+        writer,
+        "  \"timestamp\": {{\"first\": {}, \"last\": {}}},",
+        json(first),
+        json(last)
+    )?;
+    writeln!(writer, "  \"distinct_users\": {},", stats.distinct_users())?;
+    writeln!(
+        writer,
+        "  \"top_senders\": [{}],",
+        users(stats.top_senders(top))
+    )?;
+    writeln!(
+        writer,
+        "  \"top_receivers\": [{}]",
+        users(stats.top_receivers(top))
+    )?;
+    writeln!(writer, "}}")?;
+
+    Ok(())
+}
+
+/// Значение для таблицы; отсутствующее выводится прочерком
+fn optional<T: Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+/// Счётчики для JSON-объекта вида `"KEY": count`
+fn json_counts<K: Display>(counts: &BTreeMap<K, u64>) -> String {
+    counts
+        .iter()
+        .map(|(key, count)| format!("\"{}\": {}", key, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Значение для JSON; отсутствующее выводится как null
+fn json<T: Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let input_file = File::open(&args.input)?;
+    let reader = BufReader::new(input_file);
+
+    let mut stats = match args.input_format {
+        FormatType::Binary => collect_stats(BinaryFormat::new(), reader)?,
+        FormatType::Csv => collect_stats(CsvFormat::new(), reader)?,
+        FormatType::Text => collect_stats(TextFormat::new(), reader)?,
+    };
+
+    let stdout = io::stdout();
+    let writer = BufWriter::new(stdout);
+    match args.output_format {
+        ReportFormat::Table => write_table(writer, &mut stats, args.top)?,
+        ReportFormat::Json => write_json(writer, &mut stats, args.top)?,
+    }
+
+    Ok(())
+}